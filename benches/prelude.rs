@@ -14,6 +14,7 @@ impl xq::module_loader::ModuleLoader for NullModuleLoader {
         &self,
         path: &str,
         _search: Option<Vec<String>>,
+        _importer: Option<&std::path::Path>,
     ) -> xq::module_loader::Result<Vec<xq::Value>> {
         Err(xq::module_loader::ModuleLoadError::NotFoundError(
             path.to_string(),
@@ -24,7 +25,8 @@ impl xq::module_loader::ModuleLoader for NullModuleLoader {
         &self,
         path: &str,
         _search: Option<Vec<String>>,
-    ) -> xq::module_loader::Result<xq_lang::ast::Program> {
+        _importer: Option<&std::path::Path>,
+    ) -> xq::module_loader::Result<xq::module_loader::LoadedModule> {
         Err(xq::module_loader::ModuleLoadError::NotFoundError(
             path.to_string(),
         ))
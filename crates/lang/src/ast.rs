@@ -236,10 +236,19 @@ pub struct ConstantArray(pub Vec<ConstantValue>);
 #[derive(Debug, Clone, Eq, PartialEq, Default)]
 pub struct ConstantObject(pub Vec<(String, ConstantValue)>);
 
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum ImportAlias {
+    /// `'import' <string> 'as' <ident>`
+    Module(Identifier),
+    /// `'import' <string> 'as' <variable>`
+    Data(Identifier),
+}
+
 #[derive(Debug, Clone, Eq, PartialEq)]
 pub struct Import {
     pub path: String,
-    pub alias: Option<Identifier>,
+    /// [Option::None] for `include`.
+    pub alias: Option<ImportAlias>,
     pub meta: Option<ConstantObject>,
 }
 
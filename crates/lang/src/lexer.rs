@@ -306,7 +306,7 @@ impl<'input> IntoIterator for Lexer<'input> {
                     Token::Keyword(Keyword::Catch) => {
                         self.close_to_try()?;
                     }
-                    Token::Keyword(Keyword::Reduce | Keyword::Foreach) => {
+                    Token::Keyword(Keyword::Reduce | Keyword::Foreach | Keyword::Import) => {
                         self.open_balancing(Token::Keyword(Keyword::As));
                     }
                     Token::Keyword(Keyword::As) => {
@@ -534,9 +534,10 @@ lexer! {
         },
         ($digit+ | $digit+ '.' $digit* | $digit* '.' $digit+) (['e' 'E'] (['+' '-']? $digit+))? =? |lexer| {
             use std::str::FromStr;
-            let parsed = crate::Number::from_str(lexer.match_())
-                .map_err(|_| LexicalError::InvalidNumber(lexer.match_().to_string()))
-                .map(Token::Number);
+            let literal = lexer.match_();
+            let parsed = f64::from_str(literal)
+                .map_err(|_| LexicalError::InvalidNumber(literal.to_string()))
+                .map(|_| Token::Number(literal.into()));
             lexer.return_(parsed)
         },
         '"' => |lexer| {
@@ -656,12 +657,12 @@ mod test {
         assert_lex(
             r#"2 12 1e3 1.5 .2 .3e-1"#,
             &[
-                Token::Number(2.0.into()),
-                Token::Number(12.0.into()),
-                Token::Number(1000.0.into()),
-                Token::Number(1.5.into()),
-                Token::Number(0.2.into()),
-                Token::Number(0.03.into()),
+                Token::Number("2".into()),
+                Token::Number("12".into()),
+                Token::Number("1e3".into()),
+                Token::Number("1.5".into()),
+                Token::Number(".2".into()),
+                Token::Number(".3e-1".into()),
             ],
         );
     }
@@ -677,9 +678,9 @@ mod test {
             &[
                 Token::StringStart,
                 Token::InterpolationStart,
-                Token::Number(1.0.into()),
+                Token::Number("1".into()),
                 Token::Plus,
-                Token::Number(2.0.into()),
+                Token::Number("2".into()),
                 Token::InterpolationEnd,
                 Token::StringEnd,
             ],
@@ -3,12 +3,26 @@ pub mod ast;
 pub mod lexer;
 
 use lalrpop_util::lalrpop_mod;
-use ordered_float::OrderedFloat;
 use thiserror::Error;
 
 lalrpop_mod!(#[allow(clippy::all, clippy::pedantic, clippy::restriction, clippy::nursery, unused_imports)] pub parser, "/jq.rs");
 
-pub(crate) type Number = OrderedFloat<f64>;
+/// A number literal, kept as it was written so that it can be evaluated without losing precision.
+#[derive(Debug, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub struct Number(String);
+
+impl Number {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl From<&str> for Number {
+    fn from(literal: &str) -> Self {
+        Self(literal.to_string())
+    }
+}
+
 pub type ParseResult<T> = Result<T, ParseError>;
 type Loc = lexer::Loc;
 
@@ -178,4 +192,35 @@ mod test {
         assert!(parse_query("{,}").is_err());
         Ok(())
     }
+
+    #[test]
+    fn test_import() -> ParseResult<()> {
+        let program = super::parse_program(r#"import "a" as a; include "b" {search: "."}; a::f"#)?;
+        assert_eq!(
+            program.imports,
+            vec![
+                ast::Import {
+                    path: "a".to_string(),
+                    alias: Some(ast::ImportAlias::Module(ast::Identifier::from("a"))),
+                    meta: None,
+                },
+                ast::Import {
+                    path: "b".to_string(),
+                    alias: None,
+                    meta: Some(ast::ConstantObject(vec![(
+                        "search".to_string(),
+                        ast::ConstantValue::Primitive(ast::ConstantPrimitive::String(
+                            ".".to_string()
+                        ))
+                    )])),
+                },
+            ]
+        );
+        super::parse_program(r#"import "a" as a; def f: a::f;"#)?;
+        assert_eq!(
+            super::parse_program(r#"import "a" as $a; $a::a"#)?.imports[0].alias,
+            Some(ast::ImportAlias::Data(ast::Identifier::from("a")))
+        );
+        Ok(())
+    }
 }
@@ -1,9 +1,213 @@
-use std::iter::Once;
+use std::{
+    fmt::Display,
+    fs::File,
+    io::{BufRead, BufReader, Read},
+    iter::Once,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+};
 
 use xq::{util::SharedIterator, Array, InputError, Value};
 
 type ResultValue = Result<Value, InputError>;
 
+/// Tracks the file and the position in it that inputs are being read from for `input_filename`
+/// and `input_line_number`, and whether some of the files couldn't be read.
+///
+/// As jq does, input is read in chunks that end at line breaks, and the line number is the number
+/// of lines read so far including their line breaks. So a value is on the line of the number,
+/// unless it was the last line of the file without a line break.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct InputFileStatus {
+    filename: Arc<Mutex<Option<String>>>,
+    /// The number of lines read so far in the current file.
+    line: Arc<AtomicUsize>,
+    /// The number of bytes consumed by the parser so far in the current file.
+    offset: Arc<AtomicUsize>,
+    failed: Arc<AtomicBool>,
+}
+
+impl InputFileStatus {
+    pub(crate) fn filename(&self) -> Value {
+        match &*self.filename.lock().unwrap() {
+            Some(filename) => Value::string(filename.clone()),
+            None => Value::Null,
+        }
+    }
+
+    pub(crate) fn line_number(&self) -> Value {
+        Value::number(self.line.load(Ordering::Relaxed))
+    }
+
+    /// Where the parser currently is, as `<file>:<line>, byte <offset>` with the line number of
+    /// `input_line_number`.
+    pub(crate) fn location(&self) -> String {
+        let filename = self.filename.lock().unwrap();
+        format!(
+            "{}:{}, byte {}",
+            filename.as_deref().unwrap_or("<stdin>"),
+            self.line.load(Ordering::Relaxed),
+            self.offset.load(Ordering::Relaxed)
+        )
+    }
+
+    pub(crate) fn enter(&self, path: &Path) {
+        *self.filename.lock().unwrap() = Some(path.display().to_string());
+        self.set_position(0, 0);
+    }
+
+    pub(crate) fn set_position(&self, line: usize, offset: usize) {
+        self.line.store(line, Ordering::Relaxed);
+        self.offset.store(offset, Ordering::Relaxed);
+    }
+
+    /// Wraps the reader to give its content in chunks and track the position in it.
+    pub(crate) fn track<R: BufRead>(&self, reader: R) -> TrackingReader<R> {
+        TrackingReader {
+            inner: reader,
+            chunk: vec![],
+            consumed: 0,
+            status: self.clone(),
+        }
+    }
+
+    pub(crate) fn report(&self, path: &Path, error: impl Display) {
+        eprintln!("Error: {}: {error}", path.display());
+        self.failed.store(true, Ordering::Relaxed);
+    }
+
+    /// Reports an error in the content of the current file with the location.
+    pub(crate) fn report_invalid_input(&self, error: impl Display) {
+        eprintln!("Error (at {}): {error}", self.location());
+        self.failed.store(true, Ordering::Relaxed);
+    }
+
+    pub(crate) fn has_failed(&self) -> bool {
+        self.failed.load(Ordering::Relaxed)
+    }
+}
+
+/// The largest number of bytes read at once, which is the one of jq as it counts lines by chunks.
+/// Long lines are read in several chunks, so memory doesn't grow with the length of a line.
+const CHUNK_SIZE: u64 = 4095;
+
+pub(crate) struct TrackingReader<R> {
+    inner: R,
+    /// The chunk being given, and how many bytes of it were consumed.
+    chunk: Vec<u8>,
+    consumed: usize,
+    status: InputFileStatus,
+}
+
+impl<R: BufRead> Read for TrackingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let available = self.fill_buf()?;
+        let n = available.len().min(buf.len());
+        buf[..n].copy_from_slice(&available[..n]);
+        self.consume(n);
+        Ok(n)
+    }
+}
+
+impl<R: BufRead> BufRead for TrackingReader<R> {
+    fn fill_buf(&mut self) -> std::io::Result<&[u8]> {
+        if self.consumed == self.chunk.len() {
+            self.chunk.clear();
+            self.consumed = 0;
+            (&mut self.inner)
+                .take(CHUNK_SIZE)
+                .read_until(b'\n', &mut self.chunk)?;
+            if self.chunk.last() == Some(&b'\n') {
+                self.status.line.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        Ok(&self.chunk[self.consumed..])
+    }
+
+    fn consume(&mut self, amt: usize) {
+        let amt = amt.min(self.chunk.len() - self.consumed);
+        self.consumed += amt;
+        self.status.offset.fetch_add(amt, Ordering::Relaxed);
+    }
+}
+
+/// Reads values from the files in order as one stream. Files that couldn't be opened are
+/// reported and skipped, and so are the rest of the files that had an error while reading.
+pub(crate) fn read_files<F, I>(
+    paths: Vec<PathBuf>,
+    status: InputFileStatus,
+    read: F,
+) -> impl Iterator<Item = ResultValue>
+where
+    F: Fn(BufReader<File>) -> I,
+    I: Iterator<Item = ResultValue>,
+{
+    paths.into_iter().flat_map(move |path| {
+        let values = match File::open(&path) {
+            Ok(file) => {
+                status.enter(&path);
+                Some(read(BufReader::new(file)))
+            }
+            Err(e) => {
+                status.report(&path, e);
+                None
+            }
+        };
+        let status = status.clone();
+        values
+            .into_iter()
+            .flatten()
+            .map_while(move |value| value.map_err(|e| status.report_invalid_input(e)).ok())
+            .map(Ok)
+    })
+}
+
+/// The record separator that precedes each value in RFC 7464 JSON text sequences.
+pub(crate) const RECORD_SEPARATOR: u8 = 0x1e;
+
+/// Reads values from RFC 7464 JSON text sequences, where each value is preceded by
+/// [RECORD_SEPARATOR]. A record that couldn't be parsed, e.g. one that was truncated, is reported
+/// and skipped, and reading resumes from the next record.
+pub(crate) fn seq_values<R: BufRead>(
+    reader: R,
+    status: InputFileStatus,
+) -> impl Iterator<Item = ResultValue> {
+    reader
+        .split(RECORD_SEPARATOR)
+        .flat_map(move |record| -> Vec<ResultValue> {
+            let record = match record {
+                Ok(record) => record,
+                Err(e) => return vec![Err(InputError::new(e))],
+            };
+            let values = serde_json::Deserializer::from_slice(&record)
+                .into_iter::<Value>()
+                .collect::<Result<Vec<_>, _>>();
+            match values {
+                Ok(values) if is_truncated_scalar(&record, values.last()) => {
+                    status.report_invalid_input("Potentially truncated top-level value");
+                    vec![]
+                }
+                Ok(values) => values.into_iter().map(Ok).collect(),
+                Err(e) => {
+                    status.report_invalid_input(format!("Truncated record: {e}"));
+                    vec![]
+                }
+            }
+        })
+}
+
+/// Whether the record ends with a number, `true`, `false` or `null` without any whitespace after
+/// it, which can't be told apart from a truncated one as RFC 7464 describes.
+fn is_truncated_scalar(record: &[u8], last: Option<&Value>) -> bool {
+    !matches!(
+        last,
+        None | Some(Value::String(_) | Value::Array(_) | Value::Object(_))
+    ) && !record.last().is_some_and(u8::is_ascii_whitespace)
+}
+
 pub(crate) trait Input {
     type SingleInputIterator: Iterator<Item = ResultValue>;
     type ContextsIterator: Iterator<Item = ResultValue>;
@@ -0,0 +1,151 @@
+use std::io::{self, Write};
+
+use serde::{ser::SerializeMap, Serialize, Serializer};
+use serde_json::ser::{CompactFormatter, Formatter, PrettyFormatter};
+use xq::{LiteralPreservingJson, Value};
+
+/// How JSON values are written, which corresponds to `--indent`, `--tab`, `--ascii-output` and
+/// `--sort-keys` of jq.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct JsonOutputOptions {
+    /// The bytes to indent with, or `None` for compact output.
+    pub(crate) indent: Option<Vec<u8>>,
+    pub(crate) ascii: bool,
+    pub(crate) sort_keys: bool,
+}
+
+impl JsonOutputOptions {
+    pub(crate) fn write<W: Write>(
+        &self,
+        writer: W,
+        value: &Value,
+        styler: Option<colored_json::Styler>,
+    ) -> serde_json::Result<()> {
+        let formatter = JsonFormatter {
+            pretty: self.indent.as_deref().map(PrettyFormatter::with_indent),
+            ascii: self.ascii,
+        };
+        match styler {
+            Some(styler) => {
+                let formatter = colored_json::ColoredFormatter::with_styler(formatter, styler);
+                self.serialize(
+                    &mut serde_json::Serializer::with_formatter(writer, formatter),
+                    value,
+                )
+            }
+            None => self.serialize(
+                &mut serde_json::Serializer::with_formatter(writer, formatter),
+                value,
+            ),
+        }
+    }
+
+    fn serialize<S: Serializer>(&self, serializer: S, value: &Value) -> Result<S::Ok, S::Error> {
+        if self.sort_keys {
+            SortedKeys(value).serialize(serializer)
+        } else {
+            LiteralPreservingJson(value).serialize(serializer)
+        }
+    }
+}
+
+/// Serializes objects with their keys sorted, recursively.
+struct SortedKeys<'a>(&'a Value);
+
+impl Serialize for SortedKeys<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self.0 {
+            Value::Array(arr) => serializer.collect_seq(arr.iter().map(SortedKeys)),
+            Value::Object(obj) => {
+                let mut entries: Vec<_> = obj.iter().collect();
+                entries.sort_by_key(|(k, _)| *k);
+                let mut map = serializer.serialize_map(Some(entries.len()))?;
+                for (k, v) in entries {
+                    map.serialize_entry(k.as_str(), &SortedKeys(v))?;
+                }
+                map.end()
+            }
+            value => LiteralPreservingJson(value).serialize(serializer),
+        }
+    }
+}
+
+/// Either compact or pretty formatter, that optionally escapes non-ASCII characters.
+struct JsonFormatter<'a> {
+    pretty: Option<PrettyFormatter<'a>>,
+    ascii: bool,
+}
+
+macro_rules! delegate {
+    ($($name:ident),*) => {
+        $(
+            fn $name<W: ?Sized + Write>(&mut self, writer: &mut W) -> io::Result<()> {
+                match &mut self.pretty {
+                    Some(pretty) => pretty.$name(writer),
+                    None => CompactFormatter.$name(writer),
+                }
+            }
+        )*
+    };
+}
+
+impl Formatter for JsonFormatter<'_> {
+    delegate!(
+        begin_array,
+        end_array,
+        begin_object,
+        end_object,
+        end_array_value,
+        end_object_value
+    );
+
+    fn begin_array_value<W: ?Sized + Write>(
+        &mut self,
+        writer: &mut W,
+        first: bool,
+    ) -> io::Result<()> {
+        match &mut self.pretty {
+            Some(pretty) => pretty.begin_array_value(writer, first),
+            None => CompactFormatter.begin_array_value(writer, first),
+        }
+    }
+
+    fn begin_object_key<W: ?Sized + Write>(
+        &mut self,
+        writer: &mut W,
+        first: bool,
+    ) -> io::Result<()> {
+        match &mut self.pretty {
+            Some(pretty) => pretty.begin_object_key(writer, first),
+            None => CompactFormatter.begin_object_key(writer, first),
+        }
+    }
+
+    fn begin_object_value<W: ?Sized + Write>(&mut self, writer: &mut W) -> io::Result<()> {
+        match &mut self.pretty {
+            Some(pretty) => pretty.begin_object_value(writer),
+            None => CompactFormatter.begin_object_value(writer),
+        }
+    }
+
+    fn write_string_fragment<W: ?Sized + Write>(
+        &mut self,
+        writer: &mut W,
+        fragment: &str,
+    ) -> io::Result<()> {
+        if !self.ascii || fragment.is_ascii() {
+            return writer.write_all(fragment.as_bytes());
+        }
+        for c in fragment.chars() {
+            if c.is_ascii() {
+                writer.write_all(&[c as u8])?;
+            } else {
+                let mut buf = [0; 2];
+                for unit in c.encode_utf16(&mut buf) {
+                    write!(writer, "\\u{unit:04x}")?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
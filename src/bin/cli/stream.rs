@@ -0,0 +1,248 @@
+use std::io::{self, BufRead};
+
+use xq::{Array, InputError, Object, Value};
+
+type ResultValue = Result<Value, InputError>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Container {
+    Array,
+    Object,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Expect {
+    Value,
+    CommaOrClose,
+}
+
+/// Reads json values from the reader as the events of `--stream` of jq, i.e. `[path, leaf]` for
+/// each leaf and `[path]` for the end of each non-empty array or object where `path` is the one of
+/// its last element. Only the path to the current position is held in memory, so documents of
+/// any size can be read.
+pub(crate) struct JsonStream<R> {
+    reader: R,
+    containers: Vec<Container>,
+    path: Vec<Value>,
+    expect: Expect,
+    failed: bool,
+}
+
+impl<R: BufRead> JsonStream<R> {
+    pub(crate) fn new(reader: R) -> Self {
+        Self {
+            reader,
+            containers: vec![],
+            path: vec![],
+            expect: Expect::Value,
+            failed: false,
+        }
+    }
+
+    fn peek(&mut self) -> io::Result<Option<u8>> {
+        Ok(self.reader.fill_buf()?.first().copied())
+    }
+
+    fn bump(&mut self) {
+        self.reader.consume(1);
+    }
+
+    fn peek_non_whitespace(&mut self) -> io::Result<Option<u8>> {
+        while let Some(b) = self.peek()? {
+            if !b.is_ascii_whitespace() {
+                return Ok(Some(b));
+            }
+            self.bump();
+        }
+        Ok(None)
+    }
+
+    fn expect_non_whitespace(&mut self) -> io::Result<u8> {
+        self.peek_non_whitespace()?
+            .ok_or_else(|| invalid_data("Unexpected end of input"))
+    }
+
+    /// Reads a string or a scalar token, and parses it as a json value.
+    fn scalar(&mut self) -> io::Result<Value> {
+        let mut token = vec![];
+        if self.peek()? == Some(b'"') {
+            token.push(b'"');
+            self.bump();
+            let mut escaped = false;
+            loop {
+                let b = self
+                    .peek()?
+                    .ok_or_else(|| invalid_data("Unterminated string"))?;
+                token.push(b);
+                self.bump();
+                match b {
+                    b'"' if !escaped => break,
+                    b'\\' => escaped = !escaped,
+                    _ => escaped = false,
+                }
+            }
+        } else {
+            while let Some(b) = self.peek()? {
+                if b.is_ascii_whitespace() || b",:[]{}\"".contains(&b) {
+                    break;
+                }
+                token.push(b);
+                self.bump();
+            }
+        }
+        serde_json::from_slice(&token).map_err(io::Error::from)
+    }
+
+    fn key(&mut self) -> io::Result<Value> {
+        if self.expect_non_whitespace()? != b'"' {
+            return Err(invalid_data("Expected an object key"));
+        }
+        let key = self.scalar()?;
+        if self.expect_non_whitespace()? != b':' {
+            return Err(invalid_data("Expected `:` after an object key"));
+        }
+        self.bump();
+        Ok(key)
+    }
+
+    fn event(&self, leaf: Option<Value>) -> Value {
+        let path = Array::from_vec(self.path.clone()).into();
+        Array::from_vec(std::iter::once(path).chain(leaf).collect()).into()
+    }
+
+    fn leaf(&mut self, leaf: Value) -> Value {
+        self.expect = if self.containers.is_empty() {
+            Expect::Value
+        } else {
+            Expect::CommaOrClose
+        };
+        self.event(Some(leaf))
+    }
+
+    fn step(&mut self) -> io::Result<Option<Value>> {
+        loop {
+            let b = match self.peek_non_whitespace()? {
+                Some(b) => b,
+                None if self.containers.is_empty() => return Ok(None),
+                None => return Err(invalid_data("Unexpected end of input")),
+            };
+            match (self.expect, b) {
+                (Expect::Value, b'[') => {
+                    self.bump();
+                    if self.expect_non_whitespace()? == b']' {
+                        self.bump();
+                        return Ok(Some(self.leaf(Array::new().into())));
+                    }
+                    self.containers.push(Container::Array);
+                    self.path.push(Value::number(0));
+                }
+                (Expect::Value, b'{') => {
+                    self.bump();
+                    if self.expect_non_whitespace()? == b'}' {
+                        self.bump();
+                        return Ok(Some(self.leaf(Object::new().into())));
+                    }
+                    let key = self.key()?;
+                    self.containers.push(Container::Object);
+                    self.path.push(key);
+                }
+                (Expect::Value, b']' | b'}' | b',' | b':') => {
+                    return Err(invalid_data(format!("Unexpected `{}`", b as char)))
+                }
+                (Expect::Value, _) => {
+                    let scalar = self.scalar()?;
+                    return Ok(Some(self.leaf(scalar)));
+                }
+                (Expect::CommaOrClose, b',') => {
+                    self.bump();
+                    let next = match self.containers.last() {
+                        Some(Container::Array) => match self.path.last() {
+                            Some(Value::Number(i)) => Value::number(i.clone() + 1.into()),
+                            _ => unreachable!("Path to an array element should end with a number"),
+                        },
+                        _ => self.key()?,
+                    };
+                    *self.path.last_mut().unwrap() = next;
+                    self.expect = Expect::Value;
+                }
+                (Expect::CommaOrClose, b']' | b'}') => {
+                    let expected = match self.containers.last() {
+                        Some(Container::Array) => b']',
+                        _ => b'}',
+                    };
+                    if b != expected {
+                        return Err(invalid_data(format!("Unexpected `{}`", b as char)));
+                    }
+                    self.bump();
+                    let event = self.event(None);
+                    self.containers.pop();
+                    self.path.pop();
+                    if self.containers.is_empty() {
+                        self.expect = Expect::Value;
+                    }
+                    return Ok(Some(event));
+                }
+                (Expect::CommaOrClose, b) => {
+                    return Err(invalid_data(format!(
+                        "Expected `,` or the end of the container but got `{}`",
+                        b as char
+                    )))
+                }
+            }
+        }
+    }
+}
+
+impl<R: BufRead> Iterator for JsonStream<R> {
+    type Item = ResultValue;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+        self.step()
+            .map_err(|e| {
+                self.failed = true;
+                InputError::new(e)
+            })
+            .transpose()
+    }
+}
+
+/// Turns a value that was read at once into the events of `--stream`.
+pub(crate) fn value_to_stream(value: &Value) -> Vec<Value> {
+    fn walk(value: &Value, path: &mut Vec<Value>, events: &mut Vec<Value>) {
+        let children: Vec<(Value, &Value)> = match value {
+            Value::Array(arr) if !arr.is_empty() => arr
+                .iter()
+                .enumerate()
+                .map(|(i, v)| (Value::number(i), v))
+                .collect(),
+            Value::Object(obj) if !obj.is_empty() => obj
+                .iter()
+                .map(|(k, v)| (Value::String(k.clone()), v))
+                .collect(),
+            leaf => {
+                let event = vec![Array::from_vec(path.clone()).into(), leaf.clone()];
+                events.push(Array::from_vec(event).into());
+                return;
+            }
+        };
+        let mut last = None;
+        for (key, child) in children {
+            path.push(key);
+            walk(child, path, events);
+            last = path.pop();
+        }
+        path.extend(last);
+        events.push(Array::from_vec(vec![Array::from_vec(path.clone()).into()]).into());
+        path.pop();
+    }
+    let mut events = vec![];
+    walk(value, &mut vec![], &mut events);
+    events
+}
+
+fn invalid_data(message: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.into())
+}
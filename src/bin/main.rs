@@ -1,6 +1,8 @@
 use std::{
+    fs::File,
     io::{stdin, stdout, BufRead, Read, Write},
     path::PathBuf,
+    process::ExitCode,
 };
 
 use anyhow::{anyhow, Context, Result};
@@ -8,9 +10,18 @@ use clap::Parser;
 use clap_verbosity_flag::Verbosity;
 use cli::input::Input;
 use is_terminal::IsTerminal;
-use xq::{module_loader::PreludeLoader, run_query, InputError, Value};
+use xq::{
+    compile::compiler::Compiler,
+    module_loader::FileSystemModuleLoader,
+    vm::{machine::DebugSink, QueryExecutionError},
+    Array, CompiledQuery, InputError, LiteralPreservingJson, Object, Value,
+};
 
-use crate::cli::input::Tied;
+use crate::cli::{
+    input::{read_files, seq_values, InputFileStatus, Tied, RECORD_SEPARATOR},
+    output::JsonOutputOptions,
+    stream::{value_to_stream, JsonStream},
+};
 
 mod cli;
 
@@ -18,20 +29,73 @@ mod cli;
 #[clap(author, about, version)]
 #[clap(long_version(option_env!("LONG_VERSION").unwrap_or(env!("CARGO_PKG_VERSION"))))]
 struct Cli {
-    /// The query to run
-    #[clap(default_value = ".")]
-    query: String,
+    /// The query to run, defaults to `.`.
+    /// With `--from-file`, this is taken as the first of the positional args
+    query: Option<String>,
 
     /// Read query from a file instead of arg
     #[clap(
         name = "file",
         short = 'f',
         long = "from-file",
-        conflicts_with = "query",
         value_hint = clap::ValueHint::FilePath
     )]
     query_file: Option<PathBuf>,
 
+    /// Search modules in the directory, can be specified multiple times.
+    /// Defaults to `~/.jq`, `$ORIGIN/../lib/jq` and `$ORIGIN/../lib`
+    #[clap(
+        short = 'L',
+        long = "library-path",
+        value_name = "DIRECTORY",
+        value_hint = clap::ValueHint::DirPath
+    )]
+    library_paths: Vec<String>,
+
+    /// Set the exit status to 1 if the last output was false or null,
+    /// or to 4 if there was no output
+    #[clap(short = 'e', long)]
+    exit_status: bool,
+
+    /// Bind `$NAME` to the string VALUE, can be specified multiple times
+    #[clap(
+        long = "arg",
+        num_args = 2,
+        value_names = ["NAME", "VALUE"],
+        allow_hyphen_values = true
+    )]
+    string_vars: Vec<String>,
+
+    /// Bind `$NAME` to the JSON TEXT, can be specified multiple times
+    #[clap(
+        long = "argjson",
+        num_args = 2,
+        value_names = ["NAME", "TEXT"],
+        allow_hyphen_values = true
+    )]
+    json_vars: Vec<String>,
+
+    /// Bind `$NAME` to an array of the values in the FILE, read in the input format
+    #[clap(long = "slurpfile", num_args = 2, value_names = ["NAME", "FILE"])]
+    slurp_file_vars: Vec<String>,
+
+    /// Bind `$NAME` to the content of the FILE as a string
+    #[clap(long = "rawfile", num_args = 2, value_names = ["NAME", "FILE"])]
+    raw_file_vars: Vec<String>,
+
+    /// Pass the remaining positional args to the query as strings in `$ARGS.positional`
+    #[clap(long = "args", group = "positional-args")]
+    string_args: bool,
+
+    /// Pass the remaining positional args to the query as JSON values in `$ARGS.positional`
+    #[clap(long = "jsonargs", group = "positional-args")]
+    json_args: bool,
+
+    /// Input files to read instead of stdin, or args to the query if `--args` or `--jsonargs`
+    /// was given
+    #[clap(value_name = "FILES_OR_ARGS", allow_negative_numbers = true)]
+    positional_args: Vec<String>,
+
     #[clap(flatten)]
     input_format: InputFormatArg,
 
@@ -42,6 +106,29 @@ struct Cli {
     verbosity: Verbosity,
 }
 
+impl Cli {
+    fn positional_args(&self) -> Vec<String> {
+        match (&self.query_file, &self.query) {
+            (Some(_), Some(first)) => std::iter::once(first)
+                .chain(&self.positional_args)
+                .cloned()
+                .collect(),
+            _ => self.positional_args.clone(),
+        }
+    }
+
+    fn input_files(&self) -> Vec<PathBuf> {
+        if self.string_args || self.json_args {
+            vec![]
+        } else {
+            self.positional_args()
+                .into_iter()
+                .map(PathBuf::from)
+                .collect()
+        }
+    }
+}
+
 #[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Debug, Default, clap::ValueEnum)]
 enum SerializationFormat {
     #[default]
@@ -77,6 +164,16 @@ struct InputFormatArg {
     /// Read input values into an array
     #[arg(short, long)]
     slurp: bool,
+
+    /// Parse the input in streaming fashion, supplying `[path, leaf]` for each leaf and `[path]`
+    /// for the end of each array or object instead of whole values
+    #[arg(long, conflicts_with = "raw_input")]
+    stream: bool,
+
+    /// Use RFC 7464 JSON text sequences (`application/json-seq`) for json input and output, where
+    /// each value is preceded by the record separator (0x1E)
+    #[arg(long)]
+    seq: bool,
 }
 
 impl InputFormatArg {
@@ -109,10 +206,30 @@ struct OutputFormatArg {
     #[clap(short, long, conflicts_with = "output-format")]
     raw_output: bool,
 
+    /// Output raw string without a newline after each output
+    #[clap(short, long, conflicts_with = "output-format")]
+    join_output: bool,
+
     /// Compact output
     #[clap(short, long, conflicts_with = "output-format")]
     compact_output: bool,
 
+    /// Indent with a tab instead of spaces
+    #[clap(long, conflicts_with_all = ["output-format", "indent"])]
+    tab: bool,
+
+    /// Indent with the given number of spaces (no more than 7), where 0 means compact output
+    #[clap(long, value_name = "N", value_parser = clap::value_parser!(u8).range(0..=7), conflicts_with = "output-format")]
+    indent: Option<u8>,
+
+    /// Sort keys of objects on output
+    #[clap(short = 'S', long, conflicts_with = "output-format")]
+    sort_keys: bool,
+
+    /// Escape non-ASCII characters in the output
+    #[clap(short, long, conflicts_with = "output-format")]
+    ascii_output: bool,
+
     /// Colorize output where possible (currently only JSON is supported)
     #[clap(short = 'C', long, group = "output-color")]
     color_output: bool,
@@ -132,6 +249,24 @@ impl OutputFormatArg {
             self.output_format
         }
     }
+
+    fn json_output_options(self) -> JsonOutputOptions {
+        let indent = if self.compact_output {
+            None
+        } else if self.tab {
+            Some(b"\t".to_vec())
+        } else {
+            match self.indent.unwrap_or(2) {
+                0 => None,
+                n => Some(vec![b' '; n as usize]),
+            }
+        };
+        JsonOutputOptions {
+            indent,
+            ascii: self.ascii_output,
+            sort_keys: self.sort_keys,
+        }
+    }
 }
 
 fn init_log(verbosity: &Verbosity) -> Result<()> {
@@ -208,23 +343,322 @@ fn get_json_style() -> colored_json::Styler {
     styler
 }
 
-fn run_with_input(cli: Cli, input: impl Input) -> Result<()> {
+fn json_values<R: Read>(reader: R) -> impl Iterator<Item = Result<Value, InputError>> {
+    serde_json::de::Deserializer::from_reader(reader)
+        .into_iter::<Value>()
+        .map(|r| r.map_err(InputError::new))
+}
+
+fn yaml_values<'de, R: Read + 'de>(
+    reader: R,
+) -> impl Iterator<Item = Result<Value, InputError>> + 'de {
+    use serde::Deserialize;
+    serde_yaml::Deserializer::from_reader(reader)
+        .map(Value::deserialize)
+        .map(|r| r.map_err(InputError::new))
+}
+
+/// Reads yaml documents one by one, splitting the input before each document start marker `---`
+/// and after each document end marker `...`, which can't be at the start of a line in the content
+/// of a document. When a document is produced, the position of the `status` is updated to the end
+/// of it, so the line number is the one of its last line as for json inputs, where the lines of
+/// the markers are counted as well. The lines in the errors of a document are relative to it.
+struct YamlDocuments<R> {
+    reader: R,
+    status: InputFileStatus,
+    /// The lines read for the next document, and whether they have something other than blank
+    /// lines, comments and directives.
+    document: Vec<u8>,
+    has_content: bool,
+    lines: usize,
+    offset: usize,
+    values: std::vec::IntoIter<Result<Value, InputError>>,
+    done: bool,
+}
+
+impl<R: BufRead> YamlDocuments<R> {
+    fn new(reader: R, status: InputFileStatus) -> Self {
+        Self {
+            reader,
+            status,
+            document: vec![],
+            has_content: false,
+            lines: 0,
+            offset: 0,
+            values: vec![].into_iter(),
+            done: false,
+        }
+    }
+
+    fn push_line(&mut self, line: &[u8], has_content: bool) {
+        self.document.extend_from_slice(line);
+        self.has_content |= has_content;
+        self.lines += usize::from(line.ends_with(b"\n"));
+        self.offset += line.len();
+    }
+
+    fn take_document(&mut self) -> (Vec<u8>, usize, usize) {
+        self.has_content = false;
+        (std::mem::take(&mut self.document), self.lines, self.offset)
+    }
+
+    /// Reads lines up to the end of the next document, and returns it with the number of lines
+    /// and bytes up to its end.
+    fn read_document(&mut self) -> std::io::Result<Option<(Vec<u8>, usize, usize)>> {
+        fn is_marker(line: &[u8], marker: &[u8]) -> bool {
+            line.strip_prefix(marker)
+                .is_some_and(|rest| rest.first().is_none_or(u8::is_ascii_whitespace))
+        }
+        let mut line = vec![];
+        loop {
+            line.clear();
+            if self.reader.read_until(b'\n', &mut line)? == 0 {
+                return Ok(self.has_content.then(|| self.take_document()));
+            }
+            if is_marker(&line, b"---") && self.has_content {
+                let document = self.take_document();
+                self.push_line(&line, true);
+                return Ok(Some(document));
+            }
+            let has_content = !matches!(
+                line.iter().find(|b| !b.is_ascii_whitespace()),
+                None | Some(b'#' | b'%')
+            );
+            self.push_line(&line, has_content);
+            if is_marker(&line, b"...") && self.has_content {
+                return Ok(Some(self.take_document()));
+            }
+        }
+    }
+}
+
+impl<R: BufRead> Iterator for YamlDocuments<R> {
+    type Item = Result<Value, InputError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(value) = self.values.next() {
+                return Some(value);
+            }
+            if self.done {
+                return None;
+            }
+            match self.read_document() {
+                Ok(Some((document, lines, offset))) => {
+                    self.status.set_position(lines, offset);
+                    let mut values = vec![];
+                    for value in yaml_values(document.as_slice()) {
+                        let failed = value.is_err();
+                        values.push(value);
+                        if failed {
+                            self.done = true;
+                            break;
+                        }
+                    }
+                    self.values = values.into_iter();
+                }
+                Ok(None) => self.done = true,
+                Err(e) => {
+                    self.done = true;
+                    return Some(Err(InputError::new(e)));
+                }
+            }
+        }
+    }
+}
+
+/// Binds `$NAME`s given by `--arg`, `--argjson`, `--slurpfile` and `--rawfile`,
+/// and `$ARGS` with them and the positional args. Values that couldn't be read or parsed are
+/// input errors as in jq.
+fn compiler_with_args(cli: &Cli) -> Result<Compiler> {
+    let mut compiler = Compiler::new();
+    let mut named = Object::new();
+    for pair in cli.string_vars.chunks_exact(2) {
+        named.insert(pair[0].clone(), Value::string(pair[1].clone()));
+    }
+    for pair in cli.json_vars.chunks_exact(2) {
+        let value: Value = serde_json::from_str(&pair[1])
+            .with_context(|| format!("Invalid JSON text passed to --argjson {}", pair[0]))?;
+        named.insert(pair[0].clone(), value);
+    }
+    for pair in cli.slurp_file_vars.chunks_exact(2) {
+        let file = std::fs::File::open(&pair[1])
+            .with_context(|| format!("Unable to open --slurpfile {} {}", pair[0], pair[1]))?;
+        let reader = std::io::BufReader::new(file);
+        let values = match cli.input_format.get() {
+            SerializationFormat::Json => json_values(reader).collect::<Result<Array, _>>(),
+            SerializationFormat::Yaml => yaml_values(reader).collect::<Result<Array, _>>(),
+        }
+        .with_context(|| format!("Invalid values in --slurpfile {} {}", pair[0], pair[1]))?;
+        named.insert(pair[0].clone(), values);
+    }
+    for pair in cli.raw_file_vars.chunks_exact(2) {
+        let content = std::fs::read_to_string(&pair[1])
+            .with_context(|| format!("Unable to read --rawfile {} {}", pair[0], pair[1]))?;
+        named.insert(pair[0].clone(), Value::string(content));
+    }
+    let positional = if cli.json_args {
+        cli.positional_args()
+            .iter()
+            .map(|arg| {
+                serde_json::from_str(arg)
+                    .with_context(|| format!("Invalid JSON text passed to --jsonargs: {arg}"))
+            })
+            .collect::<Result<Array>>()?
+    } else if cli.string_args {
+        cli.positional_args()
+            .into_iter()
+            .map(Value::string)
+            .collect()
+    } else {
+        Array::new()
+    };
+    for (name, value) in named.iter() {
+        compiler.register_global(name, value.clone());
+    }
+    let mut args = Object::new();
+    args.insert("positional".to_string(), positional);
+    args.insert("named".to_string(), named);
+    compiler.register_global("ARGS", args.into());
+    Ok(compiler)
+}
+
+/// Exit codes that follow jq.
+const EXIT_FALSY_OUTPUT: u8 = 1;
+const EXIT_INPUT_ERROR: u8 = 2;
+const EXIT_COMPILE_ERROR: u8 = 3;
+const EXIT_NO_OUTPUT: u8 = 4;
+const EXIT_RUNTIME_ERROR: u8 = 5;
+
+/// What happened while running the query, to determine the exit code.
+#[derive(Debug, Default)]
+struct Outcome {
+    /// Whether the last output was neither `false` nor `null`, or `None` if there was no output.
+    last_output_truthy: Option<bool>,
+    input_error: bool,
+    runtime_error: bool,
+    /// The exit code given to `halt` or `halt_error`.
+    halted: Option<i32>,
+}
+
+impl Outcome {
+    fn record(&mut self, result: &Result<Value, QueryExecutionError>) {
+        match result {
+            Ok(value) => {
+                self.last_output_truthy =
+                    Some(!matches!(value, Value::Null | Value::Boolean(false)));
+            }
+            Err(QueryExecutionError::InputError(_)) => self.input_error = true,
+            Err(QueryExecutionError::Halt { exit_code, .. }) => self.halted = Some(*exit_code),
+            Err(_) => self.runtime_error = true,
+        }
+    }
+
+    fn exit_code(&self, exit_status: bool) -> ExitCode {
+        let code = if let Some(exit_code) = self.halted {
+            // Exit codes are truncated to 8 bits on most platforms anyway.
+            exit_code as u8
+        } else if self.input_error {
+            EXIT_INPUT_ERROR
+        } else if self.runtime_error {
+            EXIT_RUNTIME_ERROR
+        } else if !exit_status {
+            0
+        } else {
+            match self.last_output_truthy {
+                None => EXIT_NO_OUTPUT,
+                Some(false) => EXIT_FALSY_OUTPUT,
+                Some(true) => 0,
+            }
+        };
+        ExitCode::from(code)
+    }
+}
+
+/// Writes values given to `debug` and `stderr` to stderr as compact JSON, in the way jq does.
+struct StderrDebugSink;
+
+impl DebugSink for StderrDebugSink {
+    fn debug(&self, value: &Value) {
+        if let Ok(json) = serde_json::to_string(&LiteralPreservingJson(value)) {
+            eprintln!("[\"DEBUG:\",{json}]");
+        }
+    }
+
+    fn stderr(&self, value: &Value) {
+        if let Ok(json) = serde_json::to_string(&LiteralPreservingJson(value)) {
+            eprint!("{json}");
+        }
+    }
+}
+
+fn print_error(error: QueryExecutionError, status: &InputFileStatus) -> Result<()> {
+    match error {
+        QueryExecutionError::Halt { value: None, .. } => {}
+        QueryExecutionError::Halt {
+            value: Some(Value::String(s)),
+            ..
+        } => {
+            std::io::stderr().write_all(s.as_bytes())?;
+        }
+        QueryExecutionError::Halt {
+            value: Some(value), ..
+        } => {
+            serde_json::ser::to_writer(std::io::stderr().lock(), &LiteralPreservingJson(&value))?;
+            eprintln!();
+        }
+        e => eprintln!("Error (at {}): {e:?}", status.location()),
+    }
+    Ok(())
+}
+
+fn run_with_input(cli: Cli, status: InputFileStatus, input: impl Input) -> Result<ExitCode> {
+    let mut compiler = match compiler_with_args(&cli) {
+        Ok(compiler) => compiler,
+        Err(e) => {
+            eprintln!("Error: {e:#}");
+            return Ok(ExitCode::from(EXIT_INPUT_ERROR));
+        }
+    };
+    let input_file_status = status.clone();
+    compiler.register_fn("input_filename", 0, move |_, _| {
+        Ok(input_file_status.filename())
+    });
+    let input_file_status = status.clone();
+    compiler.register_fn("input_line_number", 0, move |_, _| {
+        Ok(input_file_status.line_number())
+    });
+    let mut module_loader = if cli.library_paths.is_empty() {
+        FileSystemModuleLoader::default()
+    } else {
+        FileSystemModuleLoader::new(cli.library_paths)
+    };
     let query = if let Some(path) = cli.query_file {
         log::trace!("Read query from file {path:?}");
+        if let Some(parent) = path.parent() {
+            module_loader = module_loader.with_origin(parent);
+        }
         std::fs::read_to_string(path)?
     } else {
-        log::trace!(
-            "Read from query in arg (if it wasn't the default value): `{}`",
-            cli.query
-        );
-        cli.query
+        let query = cli.query.unwrap_or_else(|| ".".to_string());
+        log::trace!("Read from query in arg (if it wasn't the default value): `{query}`");
+        query
     };
-    let module_loader = PreludeLoader();
 
     let (context, input) = input.into_iterators();
-    let result_iterator = run_query(&query, context, input, &module_loader)
-        .map_err(|e| anyhow!("{:?}", e))
-        .with_context(|| "compile query")?;
+    let compiled = match CompiledQuery::compile_with(&query, &module_loader, compiler) {
+        Ok(compiled) => compiled
+            .with_module_loader(module_loader)
+            .with_debug_sink(StderrDebugSink),
+        Err(e) => {
+            eprintln!("Error: {:?}", anyhow!("{:?}", e).context("compile query"));
+            return Ok(ExitCode::from(EXIT_COMPILE_ERROR));
+        }
+    };
+    let mut outcome = Outcome::default();
+    let result_iterator = compiled
+        .run(context, input)
+        .inspect(|result| outcome.record(result));
 
     let output_format = cli.output_format.get();
     match output_format {
@@ -234,46 +668,31 @@ fn run_with_input(cli: Cli, input: impl Input) -> Result<()> {
                 || (is_stdout_terminal && !cli.output_format.monochrome_output);
             let color_styler = should_colorize_output.then(get_json_style);
 
+            let options = cli.output_format.json_output_options();
+            let raw_output = cli.output_format.raw_output || cli.output_format.join_output;
+            let seq = cli.input_format.seq;
+            let separator: &[u8] = if cli.output_format.join_output {
+                b""
+            } else {
+                b"\n"
+            };
+
             for value in result_iterator {
+                if seq && value.is_ok() {
+                    stdout().lock().write_all(&[RECORD_SEPARATOR])?;
+                }
                 match value {
-                    Ok(Value::String(s)) if cli.output_format.raw_output => {
-                        stdout().write_all(s.as_bytes())?;
-                        println!();
+                    Ok(Value::String(s)) if raw_output && !options.ascii => {
+                        let mut stdout = stdout().lock();
+                        stdout.write_all(s.as_bytes())?;
+                        stdout.write_all(separator)?;
                     }
                     Ok(value) => {
-                        if cli.output_format.compact_output {
-                            if let Some(styler) = color_styler {
-                                let formatter = colored_json::ColoredFormatter::with_styler(
-                                    colored_json::CompactFormatter,
-                                    styler,
-                                );
-                                formatter.write_colored_json(
-                                    &serde_json::to_value(&value)?,
-                                    &mut stdout().lock(),
-                                    colored_json::ColorMode::On,
-                                )?;
-                                println!();
-                            } else {
-                                serde_json::ser::to_writer::<_, Value>(stdout().lock(), &value)?;
-                                println!();
-                            }
-                        } else if let Some(styler) = color_styler {
-                            let formatter = colored_json::ColoredFormatter::with_styler(
-                                colored_json::PrettyFormatter::default(),
-                                styler,
-                            );
-                            formatter.write_colored_json(
-                                &serde_json::to_value(&value)?,
-                                &mut stdout().lock(),
-                                colored_json::ColorMode::On,
-                            )?;
-                            println!();
-                        } else {
-                            serde_json::ser::to_writer_pretty::<_, Value>(stdout().lock(), &value)?;
-                            println!();
-                        }
+                        let mut stdout = stdout().lock();
+                        options.write(&mut stdout, &value, color_styler)?;
+                        stdout.write_all(separator)?;
                     }
-                    Err(e) => eprintln!("Error: {e:?}"),
+                    Err(e) => print_error(e, &status)?,
                 }
             }
         }
@@ -282,67 +701,118 @@ fn run_with_input(cli: Cli, input: impl Input) -> Result<()> {
                 match value {
                     Ok(value) => serde_yaml::to_writer::<_, Value>(stdout().lock(), &value)
                         .with_context(|| "Write to output")?,
-                    Err(e) => eprintln!("Error: {e:?}"),
+                    Err(e) => print_error(e, &status)?,
                 }
             }
         }
     }
-    Ok(())
+    if status.has_failed() {
+        outcome.input_error = true;
+    }
+    Ok(outcome.exit_code(cli.exit_status))
 }
 
-fn run_with_maybe_null_input(cli: Cli, input: impl Input) -> Result<()> {
+fn run_with_maybe_null_input(
+    cli: Cli,
+    status: InputFileStatus,
+    input: impl Input,
+) -> Result<ExitCode> {
     if cli.input_format.null_input {
-        run_with_input(cli, input.null_input())
+        run_with_input(cli, status, input.null_input())
     } else {
-        run_with_input(cli, input)
+        run_with_input(cli, status, input)
     }
 }
 
 fn run_with_maybe_slurp_null_input<I: Iterator<Item = Result<Value, InputError>>>(
     args: Cli,
+    status: InputFileStatus,
     input: Tied<I>,
-) -> Result<()> {
+) -> Result<ExitCode> {
     if args.input_format.slurp {
-        run_with_maybe_null_input(args, input.slurp())
+        run_with_maybe_null_input(args, status, input.slurp())
     } else {
-        run_with_maybe_null_input(args, input)
+        run_with_maybe_null_input(args, status, input)
     }
 }
 
-fn main() -> Result<()> {
+/// Replaces a value that was read at once with its events of `--stream`.
+fn to_stream(value: Result<Value, InputError>) -> Vec<Result<Value, InputError>> {
+    match value {
+        Ok(value) => value_to_stream(&value).into_iter().map(Ok).collect(),
+        Err(e) => vec![Err(e)],
+    }
+}
+
+fn read_values<R: BufRead + 'static>(
+    format: InputFormatArg,
+    status: &InputFileStatus,
+    reader: R,
+) -> Box<dyn Iterator<Item = Result<Value, InputError>>> {
+    if format.raw_input {
+        Box::new(
+            status
+                .track(reader)
+                .lines()
+                .map(|l| l.map(Value::from).map_err(InputError::new)),
+        )
+    } else {
+        match format.get() {
+            SerializationFormat::Json if format.seq => {
+                let values = seq_values(status.track(reader), status.clone());
+                if format.stream {
+                    Box::new(values.flat_map(to_stream))
+                } else {
+                    Box::new(values)
+                }
+            }
+            SerializationFormat::Json if format.stream => {
+                Box::new(JsonStream::new(status.track(reader)))
+            }
+            SerializationFormat::Json => Box::new(json_values(status.track(reader))),
+            SerializationFormat::Yaml if format.stream => {
+                Box::new(YamlDocuments::new(reader, status.clone()).flat_map(to_stream))
+            }
+            SerializationFormat::Yaml => Box::new(YamlDocuments::new(reader, status.clone())),
+        }
+    }
+}
+
+fn main() -> Result<ExitCode> {
     let cli: Cli = Cli::parse();
     init_log(&cli.verbosity)?;
     log::debug!("Parsed argument: {cli:?}");
 
-    let stdin = stdin();
-    let mut locked = stdin.lock();
+    let input_files = cli.input_files();
+    let status = InputFileStatus::default();
+    let format = cli.input_format;
 
-    if cli.input_format.raw_input {
-        if cli.input_format.slurp {
-            let mut input = String::new();
-            locked.read_to_string(&mut input)?;
-            run_with_maybe_null_input(cli, Tied::new(std::iter::once(Ok(Value::from(input)))))
-        } else {
-            let input = locked
-                .lines()
-                .map(|l| l.map(Value::from).map_err(InputError::new));
-            run_with_maybe_null_input(cli, Tied::new(input))
+    if format.raw_input && format.slurp {
+        let mut input = String::new();
+        if input_files.is_empty() {
+            stdin().lock().read_to_string(&mut input)?;
         }
-    } else {
-        match cli.input_format.get() {
-            SerializationFormat::Json => {
-                let input = serde_json::de::Deserializer::from_reader(locked)
-                    .into_iter::<Value>()
-                    .map(|r| r.map_err(InputError::new));
-                run_with_maybe_slurp_null_input(cli, Tied::new(input))
-            }
-            SerializationFormat::Yaml => {
-                use serde::Deserialize;
-                let input = serde_yaml::Deserializer::from_reader(locked)
-                    .map(Value::deserialize)
-                    .map(|r| r.map_err(InputError::new));
-                run_with_maybe_slurp_null_input(cli, Tied::new(input))
+        for path in input_files {
+            status.enter(&path);
+            if let Err(e) = File::open(&path).and_then(|mut file| file.read_to_string(&mut input)) {
+                status.report(&path, e);
             }
         }
+        let input = Tied::new(std::iter::once(Ok(Value::from(input))));
+        run_with_maybe_null_input(cli, status, input)
+    } else {
+        let input: Box<dyn Iterator<Item = Result<Value, InputError>>> = if input_files.is_empty() {
+            read_values(format, &status, stdin().lock())
+        } else {
+            let file_status = status.clone();
+            Box::new(read_files(input_files, status.clone(), move |reader| {
+                read_values(format, &file_status, reader)
+            }))
+        };
+        if format.raw_input {
+            run_with_maybe_null_input(cli, status, Tied::new(input))
+        } else {
+            run_with_maybe_slurp_null_input(cli, status, Tied::new(input))
+        }
     }
 }
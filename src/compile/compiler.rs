@@ -2,6 +2,7 @@ use std::{
     cell::RefCell,
     collections::{HashMap, HashSet},
     fmt::{Debug, Formatter},
+    path::Path,
     rc::Rc,
     slice::from_ref,
 };
@@ -9,18 +10,24 @@ use std::{
 use itertools::Itertools;
 use thiserror::Error;
 use xq_lang::ast::{
-    self, BinaryArithmeticOp, BinaryOp, BindPattern, ConstantPrimitive, FuncArg, FuncDef,
-    Identifier, ObjectBindPatternEntry, Query, StringFragment, Suffix, Term, UpdateOp,
+    self, BinaryArithmeticOp, BinaryOp, BindPattern, ConstantObject, ConstantPrimitive,
+    ConstantValue, FuncArg, FuncDef, Identifier, Import, ImportAlias, ObjectBindPatternEntry,
+    Query, StringFragment, Suffix, Term, UpdateOp,
 };
 
 use crate::{
     data_structure::PHashMap,
     intrinsic,
     module_loader::{ModuleLoadError, ModuleLoader},
-    value::Array,
+    util::{MaybeSendSync, Rc as SharedRc},
+    value::{Array, Object},
     vm::{
-        bytecode::{ClosureAddress, NamedFn0, NamedFn1, NamedFn2},
-        Address, ByteCode, Program, ScopeId, ScopedSlot,
+        bytecode::{
+            ClosureAddress, NamedFn0, NamedFn1, NamedFn2, NativeFunction, NativeGenerator,
+            ValueIterator,
+        },
+        machine::NativeClosure,
+        Address, ByteCode, Program, Result as ExecutionResult, ScopeId, ScopedSlot,
     },
     Number, Value,
 };
@@ -62,6 +69,10 @@ pub enum CompileError {
     ModuleLoadError(#[from] ModuleLoadError),
     #[error("Unknown string formatter `{0:?}`")]
     UnknownStringFormatter(Identifier),
+    #[error("Module `{0:}` should only contain function definitions")]
+    ModuleWithQuery(String),
+    #[error("Invalid `search` in the metadata of module `{0:}`")]
+    InvalidSearchPath(String),
 }
 
 type Result<T, E = CompileError> = std::result::Result<T, E>;
@@ -189,7 +200,7 @@ impl CodeEmitter {
             ConstantPrimitive::Null => self.emit_constant(Value::Null, next),
             ConstantPrimitive::False => self.emit_constant(false, next),
             ConstantPrimitive::True => self.emit_constant(true, next),
-            ConstantPrimitive::Number(v) => self.emit_constant(Number::from(v.0), next),
+            ConstantPrimitive::Number(v) => self.emit_constant(number_literal(&v), next),
             ConstantPrimitive::String(s) => self.emit_constant(s, next),
         }
     }
@@ -294,6 +305,10 @@ impl Scope {
         );
     }
 
+    fn register_function_like(&mut self, identifier: FunctionIdentifier, function: FunctionLike) {
+        self.functions.insert(identifier, function);
+    }
+
     fn register_closure(&mut self, name: Identifier) -> ScopedSlot {
         let slot = ScopedSlot(self.id, self.next_closure_slot_id);
         self.next_closure_slot_id += 1;
@@ -350,6 +365,16 @@ pub struct Compiler {
     emitter: CodeEmitter,
     next_scope_id: ScopeId,
     scope_stack: Vec<Scope>,
+    /// The global scope right after preludes were compiled, which modules are compiled on.
+    module_base_scope: Option<Scope>,
+    /// Values to be stored to the global variable slots before running the query.
+    global_values: Vec<(ScopedSlot, Value)>,
+    /// Paths of the modules being compiled, used to detect cyclic imports.
+    import_stack: Vec<String>,
+    /// Functions and generators registered by the embedder.
+    native_functions: HashMap<FunctionIdentifier, FunctionLike>,
+    /// The value of `$ENV`, or `None` to use the environment variables of the process.
+    environment: Option<Object>,
 }
 
 struct SavedScope(Scope);
@@ -403,9 +428,74 @@ impl Compiler {
             emitter: CodeEmitter::new(),
             next_scope_id: ScopeId(1),
             scope_stack: vec![Scope::new(ScopeId(0))],
+            module_base_scope: None,
+            global_values: vec![],
+            import_stack: vec![],
+            native_functions: HashMap::new(),
+            environment: None,
         }
     }
 
+    /// Sets the value of `$ENV` and `env`, which defaults to the environment variables of the
+    /// process. Pass an empty object to hide them from queries.
+    pub fn set_environment(&mut self, environment: Object) {
+        self.environment = Some(environment);
+    }
+
+    /// Binds a global variable `$name` to the `value`, so that values can be passed to queries
+    /// without splicing them into the query string. Variables bound in the query shadow it.
+    pub fn register_global(&mut self, name: &str, value: Value) {
+        self.register_global_variable(&[Identifier(name.to_string())], value);
+    }
+
+    /// Registers a function implemented in Rust that can be called from queries as `name` with
+    /// `arity` args. Args are evaluated to values like `$arg`s, and the function is invoked with
+    /// the context and the values of them for each combination of the values.
+    ///
+    /// Functions defined in the query or the preludes take precedence over the registered ones,
+    /// which in turn take precedence over the builtin intrinsics.
+    pub fn register_fn<F>(&mut self, name: &str, arity: usize, func: F)
+    where
+        F: Fn(Value, &[Value]) -> ExecutionResult<Value> + MaybeSendSync + 'static,
+    {
+        let function = NativeFunction {
+            name: name.into(),
+            arity,
+            func: SharedRc::new(func),
+        };
+        self.native_functions.insert(
+            FunctionIdentifier(Identifier(name.to_string()), arity),
+            FunctionLike::Intrinsic(ByteCode::CallNative(function), vec![ArgType::Value; arity]),
+        );
+    }
+
+    /// Registers a generator implemented in Rust that can be called from queries as `name` with
+    /// `arity` args. Args are given to the generator as closures, which can be run on any context.
+    /// The generator can produce any number of values, and the query backtracks to the generator
+    /// to take the next value, as jq does on its generators. An error produced by the generator
+    /// stops the iteration.
+    ///
+    /// Precedence is the same as functions registered with [Compiler::register_fn].
+    pub fn register_generator<F>(&mut self, name: &str, arity: usize, func: F)
+    where
+        F: Fn(Value, Vec<NativeClosure>) -> ExecutionResult<ValueIterator>
+            + MaybeSendSync
+            + 'static,
+    {
+        let generator = NativeGenerator {
+            name: name.into(),
+            arity,
+            func: SharedRc::new(func),
+        };
+        self.native_functions.insert(
+            FunctionIdentifier(Identifier(name.to_string()), arity),
+            FunctionLike::Intrinsic(
+                ByteCode::CallNativeGenerator(generator),
+                vec![ArgType::Closure; arity],
+            ),
+        );
+    }
+
     fn current_scope(&self) -> &Scope {
         self.scope_stack
             .last()
@@ -444,6 +534,19 @@ impl Compiler {
         self.current_scope_mut().register_closure(name)
     }
 
+    /// Registers variables with the given names that refer the same slot of the global scope,
+    /// which will be initialized with the `value` on the start of the execution.
+    fn register_global_variable(&mut self, names: &[Identifier], value: Value) {
+        assert_eq!(ScopeId(0), self.current_scope().id);
+        let slot = self.allocate_variable();
+        for name in names {
+            self.current_scope_mut()
+                .variables
+                .insert(name.clone(), slot);
+        }
+        self.global_values.push((slot, value));
+    }
+
     fn register_function(&mut self, name: Identifier, function: DeclaredFunction) {
         self.current_scope_mut().register_function(name, function)
     }
@@ -501,6 +604,9 @@ impl Compiler {
             FunctionIdentifier(Identifier(name), 0) => match name.as_str() {
                 "empty" => FunctionLike::Intrinsic(ByteCode::Backtrack, vec![]),
                 "input" => FunctionLike::Intrinsic(ByteCode::Input, vec![]),
+                "modulemeta" => FunctionLike::Intrinsic(ByteCode::ModuleMeta, vec![]),
+                "debug" => FunctionLike::Intrinsic(ByteCode::Debug, vec![]),
+                "stderr" => FunctionLike::Intrinsic(ByteCode::Stderr, vec![]),
                 _ => return None,
             },
             FunctionIdentifier(Identifier(name), 1) => match name.as_str() {
@@ -534,6 +640,7 @@ impl Compiler {
         self.current_scope()
             .lookup_function(function)
             .cloned()
+            .or_else(|| self.native_functions.get(function).cloned())
             .or_else(|| Self::lookup_compilable_intrinsic(function))
             .or_else(|| {
                 intrinsic::lookup_intrinsic_fn(function)
@@ -1646,34 +1753,193 @@ impl Compiler {
         Ok(())
     }
 
+    /// Consumes nothing, produces nothing. Registers functions of the imported modules to the
+    /// current scope, and returns the functions brought by `include` so that modules can re-export them.
+    fn compile_imports<M: ModuleLoader>(
+        &mut self,
+        imports: &[Import],
+        importer: Option<&Path>,
+        module_loader: &M,
+    ) -> Result<Vec<(FunctionIdentifier, FunctionLike)>> {
+        let mut included = vec![];
+        for import in imports {
+            let search = search_paths(&import.path, import.meta.as_ref())?;
+            if let Some(ImportAlias::Data(alias)) = &import.alias {
+                let values = module_loader.load_values(&import.path, search, importer)?;
+                self.register_global_variable(
+                    &[alias.clone(), Identifier(format!("{alias}::{alias}"))],
+                    Array::from_vec(values).into(),
+                );
+                continue;
+            }
+            if self.import_stack.contains(&import.path) {
+                let mut cycle = self.import_stack.clone();
+                cycle.push(import.path.clone());
+                return Err(ModuleLoadError::CyclicImportError(import.path.clone(), cycle).into());
+            }
+            let module = module_loader.load_program(&import.path, search, importer)?;
+            self.import_stack.push(import.path.clone());
+            let exported = self.compile_module(
+                &import.path,
+                &module.program,
+                module.location.as_deref(),
+                module_loader,
+            );
+            self.import_stack.pop();
+            let exported = exported?;
+            for (FunctionIdentifier(name, arity), function) in exported {
+                match &import.alias {
+                    Some(ImportAlias::Data(_)) => unreachable!(),
+                    Some(ImportAlias::Module(alias)) => {
+                        let name = Identifier(format!("{alias}::{name}"));
+                        self.current_scope_mut()
+                            .register_function_like(FunctionIdentifier(name, arity), function);
+                    }
+                    None => {
+                        let identifier = FunctionIdentifier(name, arity);
+                        self.current_scope_mut()
+                            .register_function_like(identifier.clone(), function.clone());
+                        included.push((identifier, function));
+                    }
+                }
+            }
+        }
+        Ok(included)
+    }
+
+    /// Consumes nothing, produces nothing. Compiles functions of the module on top of the preludes,
+    /// and returns the functions that the module exports.
+    fn compile_module<M: ModuleLoader>(
+        &mut self,
+        path: &str,
+        module: &ast::Program,
+        location: Option<&Path>,
+        module_loader: &M,
+    ) -> Result<Vec<(FunctionIdentifier, FunctionLike)>> {
+        if module.query != Term::Identity.into() {
+            return Err(CompileError::ModuleWithQuery(path.to_string()));
+        }
+        let saved = self.save_scope();
+        if let Some(base) = self.module_base_scope.clone() {
+            self.restore_scope(SavedScope(base));
+        }
+        let mut exported = self.compile_imports(&module.imports, location, module_loader)?;
+        for func in &module.functions {
+            self.compile_funcdef(func)?;
+        }
+        for identifier in module
+            .functions
+            .iter()
+            .map(|func| FunctionIdentifier(func.name.clone(), func.args.len()))
+            .unique()
+        {
+            let function = self.lookup_function(&identifier)?;
+            exported.push((identifier, function));
+        }
+        self.restore_scope(saved);
+        Ok(exported)
+    }
+
     pub fn compile<M: ModuleLoader>(
         &mut self,
         ast: &ast::Program,
         module_loader: &M,
     ) -> Result<Program> {
+        let environment = self.environment.take().unwrap_or_else(process_environment);
+        self.register_global("ENV", environment.into());
         let preludes = module_loader.prelude()?;
         for prelude in preludes {
             self.compile_prelude(&prelude)?;
         }
-        if !ast.imports.is_empty() {
-            todo!()
-        }
-        if ast.module_header.is_some() {
-            todo!()
-        }
+        self.module_base_scope = Some(self.current_scope().clone());
+        self.compile_imports(&ast.imports, None, module_loader)?;
         for func in &ast.functions {
             self.compile_funcdef(func)?
         }
         let output = self.emitter.output();
         let backtrack = self.emitter.backtrack();
-        let query_start = self.compile_query(&ast.query, output)?;
+        let mut query_start = self.compile_query(&ast.query, output)?;
+        for (slot, value) in std::mem::take(&mut self.global_values) {
+            query_start = self
+                .emitter
+                .emit_normal_op(ByteCode::Store(slot), query_start);
+            query_start = self.emitter.emit_push(value, query_start);
+        }
         let new_frame = self.exit_global_scope_and_emit_new_frame(query_start);
         let entry_point = self
             .emitter
             .emit_normal_op(ByteCode::Call(new_frame), backtrack);
         Ok(Program {
-            code: self.emitter.code.clone(),
+            code: self.emitter.code.clone().into(),
             entry_point,
         })
     }
 }
+
+fn process_environment() -> Object {
+    let mut environment = Object::new();
+    for (key, value) in std::env::vars_os() {
+        environment.insert(
+            key.to_string_lossy().into_owned(),
+            Value::string(value.to_string_lossy().into_owned()),
+        );
+    }
+    environment
+}
+
+fn number_literal(literal: &xq_lang::Number) -> Number {
+    literal
+        .as_str()
+        .parse()
+        .expect("Number literals should have been validated by the lexer")
+}
+
+fn constant_to_value(value: &ConstantValue) -> Value {
+    match value {
+        ConstantValue::Primitive(ConstantPrimitive::Null) => Value::Null,
+        ConstantValue::Primitive(ConstantPrimitive::False) => false.into(),
+        ConstantValue::Primitive(ConstantPrimitive::True) => true.into(),
+        ConstantValue::Primitive(ConstantPrimitive::Number(v)) => number_literal(v).into(),
+        ConstantValue::Primitive(ConstantPrimitive::String(s)) => Value::string(s.clone()),
+        ConstantValue::Array(arr) => arr
+            .0
+            .iter()
+            .map(constant_to_value)
+            .collect::<Array>()
+            .into(),
+        ConstantValue::Object(obj) => constant_object_to_value(obj),
+    }
+}
+
+pub(crate) fn constant_object_to_value(obj: &ConstantObject) -> Value {
+    obj.0
+        .iter()
+        .map(|(k, v)| (k.clone().into(), constant_to_value(v)))
+        .collect::<Object>()
+        .into()
+}
+
+/// Extracts the `search` key of an import metadata, which can be either a string or an array of strings.
+fn search_paths(path: &str, meta: Option<&ConstantObject>) -> Result<Option<Vec<String>>> {
+    let meta = match meta {
+        Some(meta) => constant_object_to_value(meta),
+        None => return Ok(None),
+    };
+    let search = match &meta {
+        Value::Object(obj) => obj.get(&"search".to_string()).cloned(),
+        _ => None,
+    };
+    match search {
+        None => Ok(None),
+        Some(Value::String(s)) => Ok(Some(vec![s.to_string()])),
+        Some(Value::Array(arr)) => arr
+            .iter()
+            .map(|v| match v {
+                Value::String(s) => Ok(s.to_string()),
+                _ => Err(CompileError::InvalidSearchPath(path.to_string())),
+            })
+            .collect::<Result<Vec<_>>>()
+            .map(Some),
+        Some(_) => Err(CompileError::InvalidSearchPath(path.to_string())),
+    }
+}
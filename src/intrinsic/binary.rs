@@ -1,12 +1,12 @@
 use std::collections::HashSet;
 
-use num::{Float, ToPrimitive, Zero};
+use num::{ToPrimitive, Zero};
 use xq_lang::ast::BinaryArithmeticOp;
 
 use crate::{
     intrinsic::string,
     vm::{bytecode::NamedFn1, QueryExecutionError},
-    Number, Value,
+    Value,
 };
 
 pub(crate) fn binary(operator: &BinaryArithmeticOp) -> NamedFn1 {
@@ -142,11 +142,11 @@ fn modulo(lhs: Value, rhs: Value) -> Result<Value, QueryExecutionError> {
     use Value::*;
     Ok(match (lhs, rhs) {
         (Number(lhs), Number(rhs)) => {
-            let rhs = number_to_i64(rhs);
-            if rhs == 0 {
+            let rhs = rhs.truncate();
+            if rhs.is_zero() {
                 return Err(QueryExecutionError::DivModByZero);
             }
-            Value::number(number_to_i64(lhs) % rhs)
+            Value::number(lhs.truncate() % rhs)
         }
         (lhs @ (Null | Boolean(_) | Number(_) | String(_) | Array(_) | Object(_)), rhs) => {
             return Err(QueryExecutionError::IncompatibleBinaryOperator(
@@ -155,8 +155,3 @@ fn modulo(lhs: Value, rhs: Value) -> Result<Value, QueryExecutionError> {
         }
     })
 }
-
-fn number_to_i64(n: Number) -> i64 {
-    n.to_i64()
-        .unwrap_or(if n > Zero::zero() { i64::MAX } else { i64::MIN })
-}
@@ -1,7 +1,6 @@
 use std::cmp::Ordering;
 
 use itertools::Itertools;
-use num::Float;
 use xq_lang::ast::Comparator;
 
 use crate::{vm::bytecode::NamedFn1, Value};
@@ -1,8 +1,9 @@
-use std::{ops::Range, rc::Rc};
+use std::ops::Range;
 
 use num::{ToPrimitive, Zero};
 
 use crate::{
+    util::Rc,
     vm::{error::Result, machine::PathElement, QueryExecutionError},
     Array, Number, Object, Value,
 };
@@ -21,7 +22,7 @@ fn parse_and_shift_index<F: Fn(Value) -> QueryExecutionError>(
     err: F,
 ) -> Result<Option<usize>> {
     let i = match index {
-        Value::Number(i) => *i,
+        Value::Number(i) => i.clone(),
         value => return Err(err(value.clone())),
     };
     let i = number_to_isize(i);
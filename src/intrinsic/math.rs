@@ -1,6 +1,4 @@
-use num::Float;
-
-use crate::{vm::Result, Number, Value};
+use crate::{number::PrimitiveReal, vm::Result, Array, Number, Value};
 
 macro_rules! as_math_fn {
     ($name: ident) => {
@@ -17,6 +15,40 @@ macro_rules! as_math_fn {
     };
 }
 
+macro_rules! as_math_fn2 {
+    ($name: ident) => {
+        crate::vm::bytecode::NamedFn2 {
+            name: stringify!($name),
+            func: |_, a: Value, b: Value| {
+                let a = crate::intrinsic::math::number_arg(stringify!($name), a)?;
+                let b = crate::intrinsic::math::number_arg(stringify!($name), b)?;
+                crate::intrinsic::math::$name(a, b).map(Into::into)
+            },
+        }
+    };
+}
+
+macro_rules! as_math_fn3 {
+    ($name: ident) => {
+        crate::vm::bytecode::NamedFn3 {
+            name: stringify!($name),
+            func: |_, a: Value, b: Value, c: Value| {
+                let a = crate::intrinsic::math::number_arg(stringify!($name), a)?;
+                let b = crate::intrinsic::math::number_arg(stringify!($name), b)?;
+                let c = crate::intrinsic::math::number_arg(stringify!($name), c)?;
+                crate::intrinsic::math::$name(a, b, c).map(Into::into)
+            },
+        }
+    };
+}
+
+pub(crate) fn number_arg(name: &'static str, v: Value) -> Result<Number> {
+    match v {
+        Value::Number(v) => Ok(v),
+        _ => Err(crate::vm::QueryExecutionError::InvalidArgType(name, v)),
+    }
+}
+
 pub(crate) fn nan(_: Value) -> Result<Value> {
     Ok(Number::nan().into())
 }
@@ -37,14 +69,130 @@ pub(crate) fn is_infinite(v: Number) -> Result<bool> {
     Ok(v.is_infinite())
 }
 
+pub(crate) fn fabs(v: Number) -> Result<Number> {
+    Ok(v.abs())
+}
+
+pub(crate) fn frexp(v: Number) -> Result<Value> {
+    let (mantissa, exponent) = libm::frexp(v.to_primitive_real());
+    Ok(Array::from_vec(vec![Value::number(mantissa), Value::number(exponent)]).into())
+}
+
+pub(crate) fn modf(v: Number) -> Result<Value> {
+    let (fractional, integral) = libm::modf(v.to_primitive_real());
+    Ok(Array::from_vec(vec![Value::number(fractional), Value::number(integral)]).into())
+}
+
 macro_rules! pub_math_fn {
     ($($name: ident),*) => {
         $(
             pub(crate) fn $name(v: Number) -> Result<Number> {
-                Ok(v.$name())
+                Ok(v.to_primitive_real().$name().into())
+            }
+        )*
+    };
+}
+
+pub_math_fn!(sqrt, sin, cos, tan, asin, acos, atan, sinh, cosh, tanh, asinh, acosh, atanh);
+
+macro_rules! pub_rounding_fn {
+    ($($name: ident => $func: path),*) => {
+        $(
+            pub(crate) fn $name(v: Number) -> Result<Number> {
+                Ok(v.round_by($func))
             }
         )*
     };
 }
 
-pub_math_fn!(floor, sqrt, sin, cos, tan, asin, acos, atan, sinh, cosh, tanh, asinh, acosh, atanh);
+pub_rounding_fn!(
+    floor => libm::floor,
+    ceil => libm::ceil,
+    round => libm::round,
+    trunc => libm::trunc,
+    nearbyint => libm::rint
+);
+
+macro_rules! pub_libm_fn {
+    ($($name: ident => $func: path),*) => {
+        $(
+            pub(crate) fn $name(v: Number) -> Result<Number> {
+                Ok($func(v.to_primitive_real()).into())
+            }
+        )*
+    };
+    ($($name: ident($a: ident, $b: ident) => $func: path),*) => {
+        $(
+            pub(crate) fn $name($a: Number, $b: Number) -> Result<Number> {
+                Ok($func($a.to_primitive_real(), $b.to_primitive_real()).into())
+            }
+        )*
+    };
+}
+
+pub_libm_fn!(
+    cbrt => libm::cbrt,
+    exp => libm::exp,
+    exp2 => libm::exp2,
+    exp10 => libm::exp10,
+    log => libm::log,
+    log2 => libm::log2,
+    log10 => libm::log10,
+    logb => logb_real,
+    significand => significand_real,
+    gamma => libm::lgamma,
+    lgamma => libm::lgamma,
+    tgamma => libm::tgamma
+);
+
+pub_libm_fn!(
+    pow(a, b) => libm::pow,
+    atan2(a, b) => libm::atan2,
+    fmin(a, b) => libm::fmin,
+    fmax(a, b) => libm::fmax,
+    ldexp(a, b) => ldexp_real,
+    scalb(a, b) => scalb_real,
+    drem(a, b) => libm::remainder
+);
+
+pub(crate) fn fma(a: Number, b: Number, c: Number) -> Result<Number> {
+    Ok(libm::fma(
+        a.to_primitive_real(),
+        b.to_primitive_real(),
+        c.to_primitive_real(),
+    )
+    .into())
+}
+
+/// The exponent of `v` as a float, as `logb` of C does.
+fn logb_real(v: PrimitiveReal) -> PrimitiveReal {
+    if v == 0.0 {
+        PrimitiveReal::NEG_INFINITY
+    } else if !v.is_finite() {
+        v.abs()
+    } else {
+        libm::ilogb(v) as PrimitiveReal
+    }
+}
+
+/// The mantissa of `v` in the range of `[1, 2)`, as `significand` of C does.
+fn significand_real(v: PrimitiveReal) -> PrimitiveReal {
+    if v == 0.0 || !v.is_finite() {
+        v
+    } else {
+        libm::scalbn(v, -libm::ilogb(v))
+    }
+}
+
+fn ldexp_real(v: PrimitiveReal, exponent: PrimitiveReal) -> PrimitiveReal {
+    libm::ldexp(v, exponent as i32)
+}
+
+/// Multiplies `v` by 2 to the power of `exponent`, which should be integral, as `scalb` of C does.
+fn scalb_real(v: PrimitiveReal, exponent: PrimitiveReal) -> PrimitiveReal {
+    if exponent.is_nan() || (exponent.is_finite() && exponent.trunc() != exponent) {
+        PrimitiveReal::NAN
+    } else {
+        libm::scalbn(v, exponent as i32)
+    }
+}
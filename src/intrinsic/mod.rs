@@ -1,7 +1,5 @@
-use std::rc::Rc;
-
 use itertools::Itertools;
-use num::{Float, ToPrimitive};
+use num::ToPrimitive;
 use phf::phf_map;
 
 pub(crate) use self::{
@@ -14,9 +12,9 @@ pub(crate) use self::{
 };
 use crate::{
     compile::compiler::{ArgType, FunctionIdentifier},
-    util::make_owned,
+    util::{make_owned, Rc},
     vm::{
-        bytecode::{NamedFn0, NamedFn1, NamedFn2},
+        bytecode::{NamedFn0, NamedFn1, NamedFn2, NamedFn3},
         error::Result,
         ByteCode, QueryExecutionError,
     },
@@ -37,6 +35,9 @@ mod unary;
 static INTRINSICS0: phf::Map<&'static str, NamedFn0> = phf_map! {
     "error" => NamedFn0 { name: "error", func: error },
     "type" => NamedFn0 { name: "type", func: get_type },
+    "input_filename" => NamedFn0 { name: "input_filename", func: input_filename },
+    "input_line_number" => NamedFn0 { name: "input_line_number", func: input_line_number },
+    "halt" => NamedFn0 { name: "halt", func: halt },
     "length" => NamedFn0 { name: "length", func: length },
     "utf8bytelength" => NamedFn0 { name: "utf8bytelength", func: utf8_byte_length },
     "keys_unsorted" =>  NamedFn0 { name: "keys_unsorted", func: keys_unsorted },
@@ -62,7 +63,26 @@ static INTRINSICS0: phf::Map<&'static str, NamedFn0> = phf_map! {
     "isnormal" => as_math_fn!(is_normal),
     "isinfinite" => as_math_fn!(is_infinite),
     "floor" => as_math_fn!(floor),
+    "ceil" => as_math_fn!(ceil),
+    "round" => as_math_fn!(round),
+    "trunc" => as_math_fn!(trunc),
+    "nearbyint" => as_math_fn!(nearbyint),
+    "fabs" => as_math_fn!(fabs),
     "sqrt" => as_math_fn!(sqrt),
+    "cbrt" => as_math_fn!(cbrt),
+    "exp" => as_math_fn!(exp),
+    "exp2" => as_math_fn!(exp2),
+    "exp10" => as_math_fn!(exp10),
+    "log" => as_math_fn!(log),
+    "log2" => as_math_fn!(log2),
+    "log10" => as_math_fn!(log10),
+    "logb" => as_math_fn!(logb),
+    "significand" => as_math_fn!(significand),
+    "gamma" => as_math_fn!(gamma),
+    "lgamma" => as_math_fn!(lgamma),
+    "tgamma" => as_math_fn!(tgamma),
+    "frexp" => as_math_fn!(frexp),
+    "modf" => as_math_fn!(modf),
     "sin" => as_math_fn!(sin),
     "cos" => as_math_fn!(cos),
     "tan" => as_math_fn!(tan),
@@ -78,6 +98,7 @@ static INTRINSICS0: phf::Map<&'static str, NamedFn0> = phf_map! {
 };
 static INTRINSICS1: phf::Map<&'static str, NamedFn1> = phf_map! {
     "error" => NamedFn1 { name: "error", func: error1 },
+    "halt_error" => NamedFn1 { name: "halt_error", func: halt_error },
     "has" => NamedFn1 { name: "has", func: has },
     "in" => NamedFn1 { name: "in", func: |i, c| has(c, i) },
     "contains" => NamedFn1 { name: "contains", func: contains },
@@ -101,6 +122,16 @@ static INTRINSICS1: phf::Map<&'static str, NamedFn1> = phf_map! {
 static INTRINSICS2: phf::Map<&'static str, NamedFn2> = phf_map! {
     "setpath" => NamedFn2 { name: "setpath", func: path::set_path },
     "__split_match_impl" => NamedFn2 { name: "__split_match_impl", func: regex::split_match_impl },
+    "pow" => as_math_fn2!(pow),
+    "atan2" => as_math_fn2!(atan2),
+    "fmin" => as_math_fn2!(fmin),
+    "fmax" => as_math_fn2!(fmax),
+    "ldexp" => as_math_fn2!(ldexp),
+    "scalb" => as_math_fn2!(scalb),
+    "drem" => as_math_fn2!(drem),
+};
+static INTRINSICS3: phf::Map<&'static str, NamedFn3> = phf_map! {
+    "fma" => as_math_fn3!(fma),
 };
 
 pub(crate) fn lookup_intrinsic_fn(
@@ -123,6 +154,13 @@ pub(crate) fn lookup_intrinsic_fn(
                 vec![ArgType::Value, ArgType::Value],
             )
         })
+    } else if *n_args == 3 {
+        INTRINSICS3.get(&ident.0).cloned().map(|f| {
+            (
+                ByteCode::Intrinsic3(f),
+                vec![ArgType::Value, ArgType::Value, ArgType::Value],
+            )
+        })
     } else {
         None
     }
@@ -140,6 +178,37 @@ fn error1(_: Value, arg: Value) -> Result<Value> {
     error(arg)
 }
 
+fn halt(_: Value) -> Result<Value> {
+    Err(QueryExecutionError::Halt {
+        value: None,
+        exit_code: 0,
+    })
+}
+
+fn halt_error(context: Value, exit_code: Value) -> Result<Value> {
+    match &exit_code {
+        Value::Number(n) => match n.to_i32() {
+            Some(exit_code) => Err(QueryExecutionError::Halt {
+                value: Some(context),
+                exit_code,
+            }),
+            None => Err(QueryExecutionError::InvalidArgType("halt_error", exit_code)),
+        },
+        _ => Err(QueryExecutionError::InvalidArgType("halt_error", exit_code)),
+    }
+}
+
+/// Inputs are given as values, so we don't know which file they came from.
+/// Embedders that read files can override this with [crate::compile::compiler::Compiler::register_fn].
+fn input_filename(_: Value) -> Result<Value> {
+    Ok(Value::Null)
+}
+
+/// Same as [input_filename], we don't know where inputs came from.
+fn input_line_number(_: Value) -> Result<Value> {
+    Ok(Value::Null)
+}
+
 fn get_type(context: Value) -> Result<Value> {
     let ret = match context {
         Value::Null => "null",
@@ -1,10 +1,10 @@
-use std::{ops::Range, rc::Rc};
+use std::ops::Range;
 
 use itertools::{repeat_n, Itertools};
 use num::ToPrimitive;
 
 use crate::{
-    util::make_owned,
+    util::{make_owned, Rc},
     value::RcString,
     vm::{error::Result, QueryExecutionError},
     Array, Object, Value,
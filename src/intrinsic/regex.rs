@@ -1,8 +1,7 @@
-use std::rc::Rc;
-
 use onig::{Regex, RegexOptions, Syntax};
 
 use crate::{
+    util::Rc,
     vm::{QueryExecutionError, Result},
     Array, Object, Value,
 };
@@ -1,12 +1,12 @@
-use std::{borrow::Cow, fmt::Write, rc::Rc};
+use std::{borrow::Cow, fmt::Write};
 
 use itertools::Itertools;
-use num::{Float, ToPrimitive};
+use num::ToPrimitive;
 
 use crate::{
-    util::make_owned,
+    util::{make_owned, Rc},
     vm::{bytecode::NamedFn0, QueryExecutionError, Result},
-    Array, Number, Value,
+    Array, LiteralPreservingJson, Number, Value,
 };
 
 pub(crate) fn to_number(value: Value) -> Result<Value> {
@@ -84,7 +84,7 @@ pub(crate) fn implode(value: Value) -> Result<Value> {
                     Value::Number(c) => {
                         let c = c
                             .to_u32()
-                            .ok_or(QueryExecutionError::InvalidNumberAsChar(*c))?;
+                            .ok_or(QueryExecutionError::InvalidNumberAsChar(c.clone()))?;
                         let c = char::try_from(c)
                             .map_err(|_| QueryExecutionError::InvalidNumberAsChar(c.into()))?;
                         Ok(c)
@@ -115,7 +115,7 @@ pub(crate) fn split(lhs: &str, rhs: &str) -> Value {
 fn stringify_inner(value: Value) -> Rc<String> {
     match value {
         Value::String(s) => s,
-        _ => serde_json::to_string(&value)
+        _ => serde_json::to_string(&LiteralPreservingJson(&value))
             .expect("Unable to encode a value to json")
             .into(),
     }
@@ -135,7 +135,7 @@ pub(crate) fn from_json(value: Value) -> Result<Value> {
 }
 
 pub(crate) fn to_json(value: Value) -> Result<Value> {
-    Ok(serde_json::to_string(&value)
+    Ok(serde_json::to_string(&LiteralPreservingJson(&value))
         .expect("Unable to encode a value to json")
         .into())
 }
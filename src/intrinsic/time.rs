@@ -1,11 +1,10 @@
-use std::rc::Rc;
-
 use time::{Date, Month, OffsetDateTime, PrimitiveDateTime, Time, UtcOffset};
 use time_fmt::{format::format_zoned_offset_date_time, parse::parse_date_time_maybe_with_zone};
 use time_tz::{system::get_timezone, Offset, TimeZone};
 
 use crate::{
     number::PrimitiveReal,
+    util::Rc,
     value::RcString,
     vm::{QueryExecutionError, Result},
     Array, Value,
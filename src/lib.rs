@@ -13,12 +13,16 @@ use xq_lang::ParseError;
 
 use crate::{
     compile::compiler::{CompileError, Compiler},
-    module_loader::ModuleLoader,
-    vm::{machine::Machine, QueryExecutionError},
+    module_loader::{FileSystemModuleLoader, ModuleLoader},
+    util::{MaybeSendSync, Rc},
+    vm::{
+        machine::{CancellationToken, DebugSink, Limits, Machine},
+        Program, QueryExecutionError,
+    },
 };
 pub use crate::{
     number::Number,
-    value::{Array, Object, Value},
+    value::{Array, LiteralPreservingJson, Object, Value},
 };
 
 pub type InputError = vm::error::InputError;
@@ -33,6 +37,105 @@ pub enum XQError {
     QueryExecutionError(#[from] QueryExecutionError),
 }
 
+/// A query that was parsed and compiled once, and can be run many times against different inputs.
+///
+/// Cloning is cheap since the compiled [Program] is shared.
+#[derive(Debug, Clone)]
+pub struct CompiledQuery {
+    machine: Machine,
+}
+
+impl CompiledQuery {
+    /// Parses and compiles the query, along with the preludes and modules given by the module loader.
+    /// The module loader is only used for the compilation, see [CompiledQuery::with_module_loader]
+    /// for `modulemeta`.
+    pub fn compile<M>(query: &str, module_loader: &M) -> Result<Self, XQError>
+    where
+        M: ModuleLoader,
+    {
+        Self::compile_with(query, module_loader, Compiler::new())
+    }
+
+    /// Same as [CompiledQuery::compile], but with a [Compiler] configured in advance,
+    /// e.g. with native functions or global variables.
+    pub fn compile_with<M>(
+        query: &str,
+        module_loader: &M,
+        mut compiler: Compiler,
+    ) -> Result<Self, XQError>
+    where
+        M: ModuleLoader,
+    {
+        let parsed = xq_lang::parse_program(query)?;
+        log::info!("Parsed query = {:?}", parsed);
+
+        let program = compiler.compile(&parsed, module_loader)?;
+        log::info!("Compiled program = {:?}", program);
+
+        Ok(Self::new(program))
+    }
+
+    /// Wraps a program compiled with a [Compiler].
+    pub fn new(program: Program) -> Self {
+        Self {
+            machine: Machine::new(program),
+        }
+    }
+
+    /// Sets the module loader used by `modulemeta` while running the query.
+    pub fn with_module_loader<M>(self, module_loader: M) -> Self
+    where
+        M: ModuleLoader + MaybeSendSync + 'static,
+    {
+        Self {
+            machine: self.machine.with_module_loader(Rc::new(module_loader)),
+        }
+    }
+
+    /// Limits the number of byte codes executed by each run of the query, see [Machine::with_fuel].
+    pub fn with_fuel(self, fuel: u64) -> Self {
+        Self {
+            machine: self.machine.with_fuel(fuel),
+        }
+    }
+
+    /// Makes each run of the query stop once `token` was cancelled,
+    /// see [Machine::with_cancellation_token].
+    pub fn with_cancellation_token(self, token: CancellationToken) -> Self {
+        Self {
+            machine: self.machine.with_cancellation_token(token),
+        }
+    }
+
+    /// Sets where values given to `debug` and `stderr` go, see [Machine::with_debug_sink].
+    pub fn with_debug_sink<S>(self, debug_sink: S) -> Self
+    where
+        S: DebugSink + 'static,
+    {
+        Self {
+            machine: self.machine.with_debug_sink(Rc::new(debug_sink)),
+        }
+    }
+
+    /// Limits resources used by each run of the query, see [Limits].
+    pub fn with_limits(self, limits: Limits) -> Self {
+        Self {
+            machine: self.machine.with_limits(limits),
+        }
+    }
+
+    /// Runs the query against the given context and input.
+    pub fn run<C, I>(&self, context: C, input: I) -> ResultIterator<C, I>
+    where
+        C: Iterator<Item = Result<Value, InputError>>,
+        I: Iterator<Item = Result<Value, InputError>>,
+    {
+        self.machine.start(context, input)
+    }
+}
+
+/// Compiles the query with the module loader and runs it, with a default
+/// [FileSystemModuleLoader] for `modulemeta`.
 pub fn run_query<C, I, M>(
     query: &str,
     context: C,
@@ -44,17 +147,7 @@ where
     I: Iterator<Item = Result<Value, InputError>>,
     M: ModuleLoader,
 {
-    // let now = std::time::Instant::now();
-    let parsed = xq_lang::parse_program(query)?;
-    log::info!("Parsed query = {:?}", parsed);
-    // eprintln!("Parse: {:?}", now.elapsed());
-    // let now = std::time::Instant::now();
-
-    let mut compiler = Compiler::new();
-    let program = compiler.compile(&parsed, module_loader)?;
-    log::info!("Compiled program = {:?}", program);
-    // eprintln!("Compile: {:?}", now.elapsed());
-
-    let mut vm = Machine::new(program);
-    Ok(vm.start(context, input))
+    Ok(CompiledQuery::compile(query, module_loader)?
+        .with_module_loader(FileSystemModuleLoader::default())
+        .run(context, input))
 }
@@ -1,26 +1,113 @@
+use std::path::{Component, Path, PathBuf};
+
 use thiserror::Error;
-use xq_lang::{ast::Program, parse_program, ParseError};
+use xq_lang::{
+    ast::{ImportAlias, Program},
+    parse_program, ParseError,
+};
 
-use crate::{module_loader::ModuleLoadError::NotFoundError, Value};
+use crate::{
+    compile::compiler::constant_object_to_value, module_loader::ModuleLoadError::NotFoundError,
+    util::MaybeSendSync, Array, Object, Value,
+};
 
 #[derive(Debug, Error)]
 pub enum ModuleLoadError {
     #[error("Module `{0:?}` not found")]
     NotFoundError(String),
+    #[error("No module loader to load module `{0:?}`")]
+    NoModuleLoaderError(String),
+    #[error("Module path `{0:?}` is invalid: {1}")]
+    InvalidPathError(String, &'static str),
+    #[error("Module `{0:?}` is imported cyclically: {1:?}")]
+    CyclicImportError(String, Vec<String>),
     #[error(transparent)]
     ParseError(#[from] ParseError),
     #[error(transparent)]
+    DataParseError(#[from] serde_json::Error),
+    #[error(transparent)]
     IOError(#[from] std::io::Error),
 }
 
 pub type Result<T> = std::result::Result<T, ModuleLoadError>;
 
+/// A module loaded by [ModuleLoader::load_program].
+pub struct LoadedModule {
+    pub program: Program,
+    /// The file the module was loaded from, if any. This is given back to the loader as the
+    /// `importer` of the modules that this module imports.
+    pub location: Option<PathBuf>,
+}
+
+impl From<Program> for LoadedModule {
+    fn from(program: Program) -> Self {
+        Self {
+            program,
+            location: None,
+        }
+    }
+}
+
+/// `importer` is the location of the module that has the import, or `None` if it's imported from
+/// the main query, so that search paths relative to the importing file can be resolved.
 pub trait ModuleLoader {
     fn prelude(&self) -> Result<Vec<Program>>;
-    fn load_values(&self, path: &str, search: Option<Vec<String>>) -> Result<Vec<Value>>;
-    fn load_program(&self, path: &str, search: Option<Vec<String>>) -> Result<Program>;
+    fn load_values(
+        &self,
+        path: &str,
+        search: Option<Vec<String>>,
+        importer: Option<&Path>,
+    ) -> Result<Vec<Value>>;
+    fn load_program(
+        &self,
+        path: &str,
+        search: Option<Vec<String>>,
+        importer: Option<&Path>,
+    ) -> Result<LoadedModule>;
+}
+
+/// A module loader that compiled programs can hold to load modules while running, i.e. on `modulemeta`.
+pub trait SharedModuleLoader: ModuleLoader + MaybeSendSync {}
+impl<T: ModuleLoader + MaybeSendSync + ?Sized> SharedModuleLoader for T {}
+
+/// Loads the module at `path` and builds its metadata in the way `modulemeta` of jq does,
+/// i.e. the object given to the `module` directive with a `deps` array of its imports added.
+pub fn load_module_meta<M: ModuleLoader + ?Sized>(module_loader: &M, path: &str) -> Result<Value> {
+    let module = module_loader.load_program(path, None, None)?.program;
+    let mut meta = match module.module_header.as_ref().map(constant_object_to_value) {
+        Some(Value::Object(obj)) => (*obj).clone(),
+        _ => Object::new(),
+    };
+    let deps = module
+        .imports
+        .iter()
+        .map(|import| {
+            let mut dep = match import.meta.as_ref().map(constant_object_to_value) {
+                Some(Value::Object(obj)) => (*obj).clone(),
+                _ => Object::new(),
+            };
+            match &import.alias {
+                Some(ImportAlias::Module(alias)) => {
+                    dep.insert("as".to_string(), alias.0.clone());
+                    dep.insert("is_data".to_string(), false);
+                }
+                Some(ImportAlias::Data(alias)) => {
+                    dep.insert("as".to_string(), alias.0.clone());
+                    dep.insert("is_data".to_string(), true);
+                }
+                None => {
+                    dep.insert("is_data".to_string(), false);
+                }
+            }
+            dep.insert("relpath".to_string(), import.path.clone());
+            dep.into()
+        })
+        .collect::<Array>();
+    meta.insert("deps".to_string(), deps);
+    Ok(meta.into())
 }
 
+#[derive(Clone)]
 pub struct PreludeLoader();
 impl ModuleLoader for PreludeLoader {
     fn prelude(&self) -> Result<Vec<Program>> {
@@ -29,11 +116,198 @@ impl ModuleLoader for PreludeLoader {
         Ok(vec![parsed])
     }
 
-    fn load_values(&self, path: &str, _search: Option<Vec<String>>) -> Result<Vec<Value>> {
+    fn load_values(
+        &self,
+        path: &str,
+        _search: Option<Vec<String>>,
+        _importer: Option<&Path>,
+    ) -> Result<Vec<Value>> {
         Err(NotFoundError(path.to_string()))
     }
 
-    fn load_program(&self, path: &str, _search: Option<Vec<String>>) -> Result<Program> {
+    fn load_program(
+        &self,
+        path: &str,
+        _search: Option<Vec<String>>,
+        _importer: Option<&Path>,
+    ) -> Result<LoadedModule> {
+        Err(NotFoundError(path.to_string()))
+    }
+}
+
+/// Loads modules from the file system in the way jq does.
+///
+/// A module `foo/bar` is looked up as `foo/bar.jq` and then `foo/bar/bar.jq` (or with `.json` for
+/// data) in each directory of the `search` metadata followed by the library paths.
+/// In those directories, a leading `~/` is replaced with the home directory, `$ORIGIN/` with the
+/// directory of the running executable, and `./` (or `.` itself) with the directory of the
+/// importing module, or with the origin of the query for the library paths and for the imports of
+/// the query itself.
+#[derive(Clone)]
+pub struct FileSystemModuleLoader {
+    library_paths: Vec<String>,
+    origin: PathBuf,
+}
+
+impl Default for FileSystemModuleLoader {
+    fn default() -> Self {
+        Self::new(Self::default_library_paths())
+    }
+}
+
+impl FileSystemModuleLoader {
+    /// Creates a loader that searches modules in `library_paths`, as `-L` option of jq does.
+    pub fn new<I, S>(library_paths: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        Self {
+            library_paths: library_paths.into_iter().map(Into::into).collect(),
+            origin: PathBuf::from("."),
+        }
+    }
+
+    /// The search paths used by jq if no `-L` option was given.
+    pub fn default_library_paths() -> Vec<String> {
+        vec![
+            "~/.jq".to_string(),
+            "$ORIGIN/../lib/jq".to_string(),
+            "$ORIGIN/../lib".to_string(),
+        ]
+    }
+
+    /// Sets the directory that relative search paths are resolved against, which should be the
+    /// directory of the file the query was read from. Defaults to the current directory.
+    pub fn with_origin<P: Into<PathBuf>>(mut self, origin: P) -> Self {
+        self.origin = origin.into();
+        self
+    }
+
+    fn expand_search_path(&self, path: &str, relative_to: &Path) -> Option<PathBuf> {
+        if let Some(rest) = path.strip_prefix("~/") {
+            let home = std::env::var_os("HOME").or_else(|| std::env::var_os("USERPROFILE"))?;
+            Some(PathBuf::from(home).join(rest))
+        } else if path == "~" {
+            std::env::var_os("HOME")
+                .or_else(|| std::env::var_os("USERPROFILE"))
+                .map(PathBuf::from)
+        } else if let Some(rest) = path.strip_prefix("$ORIGIN/") {
+            let exe = std::env::current_exe().ok()?;
+            Some(exe.parent()?.join(rest))
+        } else if path == "." {
+            Some(relative_to.to_path_buf())
+        } else if let Some(rest) = path.strip_prefix("./") {
+            Some(relative_to.join(rest))
+        } else {
+            Some(PathBuf::from(path))
+        }
+    }
+
+    fn find(
+        &self,
+        path: &str,
+        search: Option<Vec<String>>,
+        importer: Option<&Path>,
+        extension: &str,
+    ) -> Result<PathBuf> {
+        validate_module_path(path)?;
+        let relative = Path::new(path);
+        let base_name = relative
+            .file_name()
+            .ok_or_else(|| ModuleLoadError::InvalidPathError(path.to_string(), "empty path"))?;
+        let candidates = [
+            relative.with_extension(extension),
+            relative.join(base_name).with_extension(extension),
+        ];
+        let importer_directory = importer
+            .and_then(Path::parent)
+            .unwrap_or(self.origin.as_path());
+        let directories = search
+            .into_iter()
+            .flatten()
+            .filter_map(|dir| self.expand_search_path(&dir, importer_directory))
+            .chain(
+                self.library_paths
+                    .iter()
+                    .filter_map(|dir| self.expand_search_path(dir, &self.origin)),
+            );
+        for directory in directories {
+            for candidate in &candidates {
+                let candidate = directory.join(candidate);
+                log::trace!("Look for module `{path}` at {candidate:?}");
+                if candidate.is_file() {
+                    return Ok(candidate);
+                }
+            }
+        }
         Err(NotFoundError(path.to_string()))
     }
 }
+
+/// Module paths should be relative, shouldn't go up, and shouldn't have consecutive components
+/// with the same name (i.e. `foo/foo`) to avoid the ambiguity against `foo/foo/foo.jq`.
+fn validate_module_path(path: &str) -> Result<()> {
+    let mut prev: Option<&std::ffi::OsStr> = None;
+    for component in Path::new(path).components() {
+        match component {
+            Component::Normal(name) => {
+                if prev == Some(name) {
+                    return Err(ModuleLoadError::InvalidPathError(
+                        path.to_string(),
+                        "consecutive components with the same name are not allowed",
+                    ));
+                }
+                prev = Some(name);
+            }
+            Component::CurDir => {}
+            Component::ParentDir => {
+                return Err(ModuleLoadError::InvalidPathError(
+                    path.to_string(),
+                    "`..` is not allowed",
+                ))
+            }
+            Component::RootDir | Component::Prefix(_) => {
+                return Err(ModuleLoadError::InvalidPathError(
+                    path.to_string(),
+                    "should be a relative path",
+                ))
+            }
+        }
+    }
+    Ok(())
+}
+
+impl ModuleLoader for FileSystemModuleLoader {
+    fn prelude(&self) -> Result<Vec<Program>> {
+        PreludeLoader().prelude()
+    }
+
+    fn load_values(
+        &self,
+        path: &str,
+        search: Option<Vec<String>>,
+        importer: Option<&Path>,
+    ) -> Result<Vec<Value>> {
+        let file = self.find(path, search, importer, "json")?;
+        let content = std::fs::read_to_string(file)?;
+        let values = serde_json::de::Deserializer::from_str(&content)
+            .into_iter::<Value>()
+            .collect::<std::result::Result<_, _>>()?;
+        Ok(values)
+    }
+
+    fn load_program(
+        &self,
+        path: &str,
+        search: Option<Vec<String>>,
+        importer: Option<&Path>,
+    ) -> Result<LoadedModule> {
+        let file = self.find(path, search, importer, "jq")?;
+        let content = std::fs::read_to_string(&file)?;
+        Ok(LoadedModule {
+            program: parse_program(&content)?,
+            location: Some(file),
+        })
+    }
+}
@@ -1,38 +1,339 @@
-use std::{fmt::Formatter, ops::Neg, str::FromStr};
+use std::{
+    cmp::Ordering,
+    fmt::{Debug, Display, Formatter},
+    hash::{Hash, Hasher},
+    ops::{Add, Div, Mul, Neg, Rem, Sub},
+    str::FromStr,
+};
 
-use cast::i32;
-use derive_more::{DebugCustom, Display};
+use num::{BigInt, FromPrimitive, Integer, Signed, ToPrimitive, Zero};
 use ordered_float::OrderedFloat;
-use serde::{serde_if_integer128, Deserialize, Deserializer, Serialize, Serializer};
+use serde::{
+    de::MapAccess, ser::SerializeStruct, serde_if_integer128, Deserialize, Deserializer, Serialize,
+    Serializer,
+};
+
+use crate::util::Rc;
 
 pub(crate) type PrimitiveReal = f64;
 
-#[derive(
-    Copy,
-    Clone,
-    Eq,
-    PartialEq,
-    Ord,
-    PartialOrd,
-    Hash,
-    DebugCustom,
-    Display,
-    num_derive::FromPrimitive,
-    num_derive::ToPrimitive,
-    num_derive::NumCast,
-    num_derive::NumOps,
-    num_derive::Zero,
-    num_derive::One,
-    num_derive::Num,
-    num_derive::Float,
-)]
-#[debug(fmt = "{_0}")]
-#[display(fmt = "{_0}")]
-pub struct Number(OrderedFloat<PrimitiveReal>);
+/// The key of the single-entry map that serde_json with `arbitrary_precision` gives a number
+/// literal as, and also takes to write a literal as is. This is only recognized with the
+/// `arbitrary-precision` feature.
+pub(crate) const JSON_NUMBER_TOKEN: &str = "$serde_json::private::Number";
+
+/// Integral floats up to this are kept as exact integers, as they can be converted without loss.
+const MAX_EXACT_INTEGRAL_FLOAT: PrimitiveReal = 9007199254740992.0;
+
+/// A number, that is an exact integer if possible and a float otherwise.
+///
+/// Integers are held as `i64`, or as a big integer if they don't fit, and arithmetic on integers
+/// stays exact as long as the result is an integer. A number read from a literal remembers the
+/// literal, so that it can be written out as it appeared unless it was modified.
+#[derive(Clone)]
+pub struct Number {
+    repr: Repr,
+    literal: Option<Rc<str>>,
+}
+
+#[derive(Clone)]
+enum Repr {
+    Integer(i64),
+    BigInteger(Rc<BigInt>),
+    Float(OrderedFloat<PrimitiveReal>),
+}
 
 impl Number {
-    pub(crate) fn to_primitive_real(self) -> PrimitiveReal {
-        self.0 .0
+    fn from_repr(repr: Repr) -> Self {
+        Self {
+            repr,
+            literal: None,
+        }
+    }
+
+    fn from_big_int(n: BigInt) -> Self {
+        match n.to_i64() {
+            Some(n) => Self::from_repr(Repr::Integer(n)),
+            None => Self::from_repr(Repr::BigInteger(Rc::new(n))),
+        }
+    }
+
+    fn from_float(f: PrimitiveReal) -> Self {
+        #[allow(clippy::float_cmp)]
+        let is_integral = f.trunc() == f;
+        if is_integral
+            && f.abs() <= MAX_EXACT_INTEGRAL_FLOAT
+            && !(f.is_zero() && f.is_sign_negative())
+        {
+            Self::from_repr(Repr::Integer(f as i64))
+        } else {
+            Self::from_repr(Repr::Float(OrderedFloat(f)))
+        }
+    }
+
+    /// Parses a literal that is known to be a valid JSON number.
+    fn from_json_literal(literal: &str) -> Self {
+        // `-0` is parsed as a float so that it keeps the sign.
+        let number = if literal.contains(['.', 'e', 'E']) || literal == "-0" {
+            Self::from(PrimitiveReal::from_str(literal).unwrap_or(PrimitiveReal::NAN))
+        } else if let Ok(n) = i64::from_str(literal) {
+            n.into()
+        } else {
+            BigInt::from_str(literal)
+                .map(Self::from_big_int)
+                .unwrap_or_else(|_| Self::nan())
+        };
+        if number.is_written_as(literal) {
+            number
+        } else {
+            Self {
+                literal: Some(literal.into()),
+                ..number
+            }
+        }
+    }
+
+    /// Whether this number is written as the literal without remembering it, which is the case
+    /// for most literals, so that they don't need to be allocated. Literals of floats with an
+    /// exponent are remembered, as they're displayed without one.
+    fn is_written_as(&self, literal: &str) -> bool {
+        match &self.repr {
+            Repr::Integer(n) => itoa::Buffer::new().format(*n) == literal,
+            Repr::Float(f) => {
+                f.is_finite()
+                    && !f.is_zero()
+                    && !literal.contains(['e', 'E'])
+                    && (is_short_decimal(literal)
+                        || ryu::Buffer::new().format_finite(f.0) == literal)
+            }
+            Repr::BigInteger(_) => false,
+        }
+    }
+
+    pub fn nan() -> Self {
+        Self::from_repr(Repr::Float(OrderedFloat(PrimitiveReal::NAN)))
+    }
+
+    pub fn infinity() -> Self {
+        Self::from_repr(Repr::Float(OrderedFloat(PrimitiveReal::INFINITY)))
+    }
+
+    /// The literal this number was read from, if it hasn't been modified since then and is
+    /// different from how the number is written otherwise.
+    ///
+    /// Literals in JSON input are normalized by serde_json, which writes their exponents as
+    /// `e+N` or `e-N`, e.g. `1E2` is read as `1e+2`. Literals in queries are kept as written.
+    pub fn literal(&self) -> Option<&str> {
+        self.literal.as_deref()
+    }
+
+    pub(crate) fn to_primitive_real(&self) -> PrimitiveReal {
+        match &self.repr {
+            Repr::Integer(n) => *n as PrimitiveReal,
+            Repr::BigInteger(n) => n.to_f64().unwrap_or(PrimitiveReal::NAN),
+            Repr::Float(f) => f.0,
+        }
+    }
+
+    fn to_big_int(&self) -> Option<BigInt> {
+        match &self.repr {
+            Repr::Integer(n) => Some(BigInt::from(*n)),
+            Repr::BigInteger(n) => Some((**n).clone()),
+            Repr::Float(_) => None,
+        }
+    }
+
+    /// Both of this and `other` as big integers, if they are integers.
+    fn to_big_ints(&self, other: &Self) -> Option<(BigInt, BigInt)> {
+        Some((self.to_big_int()?, other.to_big_int()?))
+    }
+
+    /// Compares this integer with a float by their exact values, so that an integer that isn't
+    /// representable as a float isn't rounded into an equal one.
+    fn cmp_integer_with_float(&self, f: PrimitiveReal) -> Ordering {
+        if f.is_nan() {
+            // NaN is greater than anything else, as in `OrderedFloat`.
+            return Ordering::Less;
+        }
+        if f.is_infinite() {
+            return if f.is_sign_positive() {
+                Ordering::Less
+            } else {
+                Ordering::Greater
+            };
+        }
+        let floor = f.floor();
+        let ordering = match (&self.repr, floor.to_i128()) {
+            (Repr::Integer(n), Some(floor)) => i128::from(*n).cmp(&floor),
+            _ => match (self.to_big_int(), BigInt::from_f64(floor)) {
+                (Some(n), Some(floor)) => n.cmp(&floor),
+                _ => unreachable!("Only integers are compared with a finite float"),
+            },
+        };
+        #[allow(clippy::float_cmp)]
+        let is_integral = floor == f;
+        if is_integral {
+            ordering
+        } else {
+            // `f` is strictly between `floor` and `floor + 1`.
+            ordering.then(Ordering::Less)
+        }
+    }
+
+    pub fn is_integer(&self) -> bool {
+        !matches!(self.repr, Repr::Float(_))
+    }
+
+    pub fn is_nan(&self) -> bool {
+        matches!(self.repr, Repr::Float(f) if f.is_nan())
+    }
+
+    pub fn is_infinite(&self) -> bool {
+        matches!(self.repr, Repr::Float(f) if f.is_infinite())
+    }
+
+    pub fn is_finite(&self) -> bool {
+        !matches!(self.repr, Repr::Float(f) if !f.is_finite())
+    }
+
+    pub fn is_normal(&self) -> bool {
+        self.to_primitive_real().is_normal()
+    }
+
+    pub fn is_sign_negative(&self) -> bool {
+        match &self.repr {
+            Repr::Integer(n) => n.is_negative(),
+            Repr::BigInteger(n) => n.is_negative(),
+            Repr::Float(f) => f.is_sign_negative(),
+        }
+    }
+
+    pub fn abs(&self) -> Self {
+        match &self.repr {
+            Repr::Integer(n) => n
+                .checked_abs()
+                .map_or_else(|| Self::from_big_int(BigInt::from(*n).abs()), Self::from),
+            Repr::BigInteger(n) => Self::from_big_int(n.abs()),
+            Repr::Float(f) => Self::from_float(f.0.abs()),
+        }
+    }
+
+    /// Rounds a float into an integral value with `f`. Integers are kept exact.
+    pub(crate) fn round_by(self, f: fn(PrimitiveReal) -> PrimitiveReal) -> Self {
+        match self.repr {
+            Repr::Integer(_) | Repr::BigInteger(_) => Self::from_repr(self.repr),
+            Repr::Float(v) => Self::from_float(f(v.0)),
+        }
+    }
+
+    /// Truncates a float toward zero, saturating it into the range of `i64`, as jq does on `%`.
+    pub(crate) fn truncate(self) -> Self {
+        match self.repr {
+            Repr::Integer(_) | Repr::BigInteger(_) => self,
+            Repr::Float(f) => f
+                .to_i64()
+                .unwrap_or(if f.0 > 0.0 { i64::MAX } else { i64::MIN })
+                .into(),
+        }
+    }
+
+    fn binary_op(
+        self,
+        rhs: Self,
+        integer_op: fn(i64, i64) -> Option<i64>,
+        big_integer_op: fn(BigInt, BigInt) -> BigInt,
+        float_op: fn(PrimitiveReal, PrimitiveReal) -> PrimitiveReal,
+    ) -> Self {
+        if let (Repr::Integer(lhs), Repr::Integer(rhs)) = (&self.repr, &rhs.repr) {
+            if let Some(n) = integer_op(*lhs, *rhs) {
+                return n.into();
+            }
+        }
+        match self.to_big_ints(&rhs) {
+            Some((lhs, rhs)) => Self::from_big_int(big_integer_op(lhs, rhs)),
+            None => Self::from_float(float_op(self.to_primitive_real(), rhs.to_primitive_real())),
+        }
+    }
+
+    /// Serializes this number in the form of its literal if it has one, or as an arbitrary
+    /// precision number if it's a big integer. This relies on `arbitrary_precision` of serde_json,
+    /// so this should only be used with a serializer of serde_json, and is the same as the plain
+    /// serialization without the `arbitrary-precision` feature.
+    pub(crate) fn serialize_as_literal<S: Serializer>(
+        &self,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        if !cfg!(feature = "arbitrary-precision") {
+            return self.serialize(serializer);
+        }
+        let literal = match (&self.literal, &self.repr) {
+            (Some(literal), _) => literal.to_string(),
+            (None, Repr::Float(f)) if f.is_zero() && f.is_sign_negative() => "-0".to_string(),
+            (None, Repr::BigInteger(n)) if n.to_i128().is_none() && n.to_u128().is_none() => {
+                n.to_string()
+            }
+            _ => return self.serialize(serializer),
+        };
+        let mut s = serializer.serialize_struct(JSON_NUMBER_TOKEN, 1)?;
+        s.serialize_field(JSON_NUMBER_TOKEN, &literal)?;
+        s.end()
+    }
+}
+
+impl PartialEq for Number {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Number {}
+
+impl PartialOrd for Number {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Number {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match (&self.repr, &other.repr) {
+            (Repr::Integer(lhs), Repr::Integer(rhs)) => lhs.cmp(rhs),
+            (Repr::Float(lhs), Repr::Float(rhs)) => lhs.cmp(rhs),
+            (_, Repr::Float(rhs)) => self.cmp_integer_with_float(rhs.0),
+            (Repr::Float(lhs), _) => other.cmp_integer_with_float(lhs.0).reverse(),
+            _ => match self.to_big_ints(other) {
+                Some((lhs, rhs)) => lhs.cmp(&rhs),
+                None => unreachable!("Both should be integers"),
+            },
+        }
+    }
+}
+
+impl Hash for Number {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        // Numbers of different representations can be equal, so hash them in the common one.
+        // Equal numbers are converted into the same float, as an integer equal to a float is
+        // exactly representable as it.
+        OrderedFloat(self.to_primitive_real()).hash(state)
+    }
+}
+
+impl Display for Number {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if let Some(literal) = &self.literal {
+            return f.write_str(literal);
+        }
+        match &self.repr {
+            Repr::Integer(n) => Display::fmt(n, f),
+            Repr::BigInteger(n) => Display::fmt(n, f),
+            Repr::Float(n) => Display::fmt(n, f),
+        }
+    }
+}
+
+impl Debug for Number {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        Display::fmt(self, f)
     }
 }
 
@@ -40,24 +341,170 @@ impl FromStr for Number {
     type Err = <PrimitiveReal as FromStr>::Err;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        PrimitiveReal::from_str(s).map(|n| Number(OrderedFloat(n)))
+        if is_json_number(s) {
+            Ok(Self::from_json_literal(s))
+        } else {
+            PrimitiveReal::from_str(s).map(Self::from)
+        }
     }
 }
 
 impl Neg for Number {
     type Output = Self;
     fn neg(self) -> Self::Output {
-        Self(-self.0)
+        match self.repr {
+            // Keep the sign as jq does, which only has floats.
+            Repr::Integer(0) => Self::from_float(-0.0),
+            Repr::Integer(n) => n
+                .checked_neg()
+                .map_or_else(|| Self::from_big_int(-BigInt::from(n)), Self::from),
+            Repr::BigInteger(n) => Self::from_big_int(-(*n).clone()),
+            Repr::Float(f) => Self::from_float(-f.0),
+        }
+    }
+}
+
+impl Add for Number {
+    type Output = Self;
+    fn add(self, rhs: Self) -> Self::Output {
+        self.binary_op(rhs, i64::checked_add, |l, r| l + r, |l, r| l + r)
+    }
+}
+
+impl Sub for Number {
+    type Output = Self;
+    fn sub(self, rhs: Self) -> Self::Output {
+        self.binary_op(rhs, i64::checked_sub, |l, r| l - r, |l, r| l - r)
+    }
+}
+
+impl Mul for Number {
+    type Output = Self;
+    fn mul(self, rhs: Self) -> Self::Output {
+        self.binary_op(rhs, i64::checked_mul, |l, r| l * r, |l, r| l * r)
+    }
+}
+
+impl Div for Number {
+    type Output = Self;
+    /// Stays an integer only if it's divisible.
+    fn div(self, rhs: Self) -> Self::Output {
+        if let (Repr::Integer(lhs), Repr::Integer(rhs)) = (&self.repr, &rhs.repr) {
+            if lhs.checked_rem(*rhs) == Some(0) {
+                if let Some(n) = lhs.checked_div(*rhs) {
+                    return n.into();
+                }
+            }
+        }
+        match self.to_big_ints(&rhs) {
+            Some((lhs, rhs)) if !rhs.is_zero() && lhs.is_multiple_of(&rhs) => {
+                Self::from_big_int(lhs / rhs)
+            }
+            _ => Self::from_float(self.to_primitive_real() / rhs.to_primitive_real()),
+        }
+    }
+}
+
+impl Rem for Number {
+    type Output = Self;
+    fn rem(self, rhs: Self) -> Self::Output {
+        if let (Repr::Integer(lhs), Repr::Integer(rhs)) = (&self.repr, &rhs.repr) {
+            if let Some(n) = lhs.checked_rem(*rhs) {
+                return n.into();
+            }
+        }
+        match self.to_big_ints(&rhs) {
+            Some((_, rhs)) if rhs.is_zero() => Self::nan(),
+            Some((lhs, rhs)) => Self::from_big_int(lhs % rhs),
+            _ => Self::from_float(self.to_primitive_real() % rhs.to_primitive_real()),
+        }
+    }
+}
+
+impl Zero for Number {
+    fn zero() -> Self {
+        Self::from_repr(Repr::Integer(0))
+    }
+
+    fn is_zero(&self) -> bool {
+        match &self.repr {
+            Repr::Integer(n) => *n == 0,
+            Repr::BigInteger(_) => false,
+            Repr::Float(f) => f.is_zero(),
+        }
+    }
+}
+
+impl ToPrimitive for Number {
+    fn to_i64(&self) -> Option<i64> {
+        match &self.repr {
+            Repr::Integer(n) => Some(*n),
+            Repr::BigInteger(n) => n.to_i64(),
+            Repr::Float(f) => f.to_i64(),
+        }
+    }
+
+    fn to_u64(&self) -> Option<u64> {
+        match &self.repr {
+            Repr::Integer(n) => n.to_u64(),
+            Repr::BigInteger(n) => n.to_u64(),
+            Repr::Float(f) => f.to_u64(),
+        }
+    }
+
+    fn to_i128(&self) -> Option<i128> {
+        match &self.repr {
+            Repr::Integer(n) => Some(*n as i128),
+            Repr::BigInteger(n) => n.to_i128(),
+            Repr::Float(f) => f.to_i128(),
+        }
+    }
+
+    fn to_u128(&self) -> Option<u128> {
+        match &self.repr {
+            Repr::Integer(n) => n.to_u128(),
+            Repr::BigInteger(n) => n.to_u128(),
+            Repr::Float(f) => f.to_u128(),
+        }
+    }
+
+    fn to_f64(&self) -> Option<f64> {
+        Some(self.to_primitive_real())
     }
 }
 
-impl<T> From<T> for Number
-where
-    PrimitiveReal: cast::From<T, Output = PrimitiveReal>,
-{
-    fn from(v: T) -> Self {
-        use cast::From;
-        Self(OrderedFloat(PrimitiveReal::cast(v)))
+macro_rules! from_integer {
+    ($($t: ty),*) => {
+        $(
+            impl From<$t> for Number {
+                fn from(n: $t) -> Self {
+                    i64::try_from(n).map_or_else(
+                        |_| Self::from_big_int(BigInt::from(n)),
+                        |n| Self::from_repr(Repr::Integer(n)),
+                    )
+                }
+            }
+        )*
+    };
+}
+
+from_integer!(i8, i16, i32, i64, i128, isize, u8, u16, u32, u64, u128, usize);
+
+impl From<f32> for Number {
+    fn from(f: f32) -> Self {
+        Self::from_float(f.into())
+    }
+}
+
+impl From<f64> for Number {
+    fn from(f: f64) -> Self {
+        Self::from_float(f)
+    }
+}
+
+impl From<BigInt> for Number {
+    fn from(n: BigInt) -> Self {
+        Self::from_big_int(n)
     }
 }
 
@@ -66,18 +513,96 @@ impl Serialize for Number {
     where
         S: Serializer,
     {
-        // There's no `is_integral()`.
-        // `.fract().is_zero()` also works but `.fract()` is implemented by `self - self.trunc()`.
-        #[allow(clippy::float_cmp)]
-        if self.0 .0.trunc() == self.0 .0 {
-            if let Ok(v) = i32(self.0 .0) {
-                return serializer.serialize_i32(v);
+        match &self.repr {
+            Repr::Integer(n) => serializer.serialize_i64(*n),
+            Repr::BigInteger(n) => {
+                if let Some(n) = n.to_i128() {
+                    serializer.serialize_i128(n)
+                } else if let Some(n) = n.to_u128() {
+                    serializer.serialize_u128(n)
+                } else {
+                    serializer.serialize_f64(self.to_primitive_real())
+                }
+            }
+            Repr::Float(f) => {
+                // There's no `is_integral()`.
+                // `.fract().is_zero()` also works but `.fract()` is implemented by `self - self.trunc()`.
+                #[allow(clippy::float_cmp)]
+                if f.trunc() == f.0 && !(f.is_zero() && f.is_sign_negative()) {
+                    if let Some(n) = f.to_i64() {
+                        return serializer.serialize_i64(n);
+                    }
+                }
+                serializer.serialize_f64(f.0)
             }
         }
-        serializer.serialize_f64(self.0 .0)
     }
 }
 
+/// Whether the text is a number in the JSON grammar.
+fn is_json_number(s: &str) -> bool {
+    fn digits(s: &str) -> usize {
+        s.bytes().take_while(u8::is_ascii_digit).count()
+    }
+    let s = s.strip_prefix('-').unwrap_or(s);
+    let int = digits(s);
+    if int == 0 || (int > 1 && s.starts_with('0')) {
+        return false;
+    }
+    let mut rest = &s[int..];
+    if let Some(frac) = rest.strip_prefix('.') {
+        let n = digits(frac);
+        if n == 0 {
+            return false;
+        }
+        rest = &frac[n..];
+    }
+    if let Some(exp) = rest.strip_prefix(['e', 'E']) {
+        let exp = exp.strip_prefix(['+', '-']).unwrap_or(exp);
+        let n = digits(exp);
+        if n == 0 {
+            return false;
+        }
+        rest = &exp[n..];
+    }
+    rest.is_empty()
+}
+
+/// Whether the literal is a decimal that floats are written as, which is checked without writing
+/// the float. A decimal of up to 15 significant digits is the shortest one of its float, and
+/// written without an exponent unless it's smaller than `0.0001`.
+fn is_short_decimal(literal: &str) -> bool {
+    let unsigned = literal.strip_prefix('-').unwrap_or(literal);
+    let Some((int, frac)) = unsigned.split_once('.') else {
+        return false;
+    };
+    let significant = if int == "0" {
+        let digits = frac.trim_start_matches('0').len();
+        if frac.len() - digits >= 5 {
+            return false;
+        }
+        digits
+    } else if int.starts_with('0') {
+        return false;
+    } else {
+        int.len() + frac.len()
+    };
+    !frac.ends_with('0') && significant <= 15
+}
+
+/// Reads a number given as a map of [JSON_NUMBER_TOKEN] to its literal, after the key was read.
+pub(crate) fn number_from_json_literal<'de, A: MapAccess<'de>>(
+    mut map: A,
+) -> Result<Number, A::Error> {
+    let literal: String = map.next_value()?;
+    if !is_json_number(&literal) {
+        return Err(serde::de::Error::custom(format!(
+            "invalid number literal `{literal}`"
+        )));
+    }
+    Ok(Number::from_json_literal(&literal))
+}
+
 impl<'de> Deserialize<'de> for Number {
     fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
     where
@@ -127,6 +652,23 @@ impl<'de> Deserialize<'de> for Number {
             {
                 Ok(v.into())
             }
+
+            fn visit_map<A>(self, mut map: A) -> Result<Self::Value, A::Error>
+            where
+                A: MapAccess<'de>,
+            {
+                match map.next_key::<String>()? {
+                    Some(key)
+                        if cfg!(feature = "arbitrary-precision") && key == JSON_NUMBER_TOKEN =>
+                    {
+                        number_from_json_literal(map)
+                    }
+                    _ => Err(serde::de::Error::invalid_type(
+                        serde::de::Unexpected::Map,
+                        &self,
+                    )),
+                }
+            }
         }
         deserializer.deserialize_any(V)
     }
@@ -1,4 +1,29 @@
-use std::{cell::RefCell, rc::Rc};
+use std::cell::RefCell;
+
+/// The reference counted pointer that values and compiled programs are built on.
+/// This is [std::sync::Arc] with the `sync` feature so that they can be shared across threads,
+/// and [std::rc::Rc] otherwise.
+#[cfg(feature = "sync")]
+pub type Rc<T> = std::sync::Arc<T>;
+/// The reference counted pointer that values and compiled programs are built on.
+/// This is [std::sync::Arc] with the `sync` feature so that they can be shared across threads,
+/// and [std::rc::Rc] otherwise.
+#[cfg(not(feature = "sync"))]
+pub type Rc<T> = std::rc::Rc<T>;
+
+/// `Send + Sync` with the `sync` feature, and nothing otherwise.
+/// Functions and module loaders held by compiled programs have to implement this.
+#[cfg(feature = "sync")]
+pub trait MaybeSendSync: Send + Sync {}
+#[cfg(feature = "sync")]
+impl<T: Send + Sync + ?Sized> MaybeSendSync for T {}
+
+/// `Send + Sync` with the `sync` feature, and nothing otherwise.
+/// Functions and module loaders held by compiled programs have to implement this.
+#[cfg(not(feature = "sync"))]
+pub trait MaybeSendSync {}
+#[cfg(not(feature = "sync"))]
+impl<T: ?Sized> MaybeSendSync for T {}
 
 pub(crate) fn make_owned<T: Clone>(v: Rc<T>) -> T {
     match Rc::try_unwrap(v) {
@@ -7,10 +32,10 @@ pub(crate) fn make_owned<T: Clone>(v: Rc<T>) -> T {
     }
 }
 
-pub struct SharedIterator<I>(Rc<RefCell<I>>);
+pub struct SharedIterator<I>(std::rc::Rc<RefCell<I>>);
 impl<I> From<I> for SharedIterator<I> {
     fn from(it: I) -> Self {
-        Self(Rc::new(RefCell::new(it)))
+        Self(std::rc::Rc::new(RefCell::new(it)))
     }
 }
 impl<I> Clone for SharedIterator<I> {
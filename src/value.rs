@@ -1,28 +1,30 @@
 use std::{
     borrow::Borrow,
     cmp::Ordering,
-    collections::HashMap,
     fmt::{Debug, Display, Formatter},
     hash::{Hash, Hasher},
     iter::FromIterator,
     ops::Deref,
-    rc::Rc,
     slice::SliceIndex,
 };
 
 use derive_more::{DebugCustom, Display, Index, IndexMut, IntoIterator, IsVariant, Unwrap};
+use indexmap::IndexMap;
 use itertools::Itertools;
-use num::Float;
 use serde::{
     de::{Error, MapAccess, SeqAccess, Visitor},
     ser::{SerializeMap, SerializeSeq},
     serde_if_integer128, Deserialize, Deserializer, Serialize, Serializer,
 };
 
-use crate::Number;
+use crate::{
+    number::{number_from_json_literal, JSON_NUMBER_TOKEN},
+    util::Rc,
+    Number,
+};
 
 type Vector = Vec<Value>;
-type Map = HashMap<RcString, Value>;
+type Map = IndexMap<RcString, Value>;
 pub type RcString = Rc<String>;
 
 #[derive(
@@ -37,7 +39,9 @@ pub struct Array(#[into_iterator(owned, ref, ref_mut)] Vector);
 #[display(fmt = "{_0:?}")]
 pub struct Object(#[into_iterator(owned, ref, ref_mut)] Map);
 
-#[allow(clippy::derived_hash_with_manual_eq)] // HashMap::eq is implemented properly.
+/// Keys are kept in the order they were inserted, as jq does, but the order doesn't matter on
+/// comparison, so the hash doesn't depend on it either.
+#[allow(clippy::derived_hash_with_manual_eq)] // IndexMap::eq ignores the order of keys.
 impl Hash for Object {
     fn hash<H: Hasher>(&self, state: &mut H) {
         for (key, value) in self.0.iter().sorted_unstable_by_key(|e| e.0) {
@@ -129,6 +133,11 @@ impl Object {
         self.0.keys()
     }
 
+    /// Returns the entry at `index` in insertion order.
+    pub fn get_index(&self, index: usize) -> Option<(&RcString, &Value)> {
+        self.0.get_index(index)
+    }
+
     pub fn get<Q: ?Sized>(&self, key: &Q) -> Option<&Value>
     where
         RcString: Borrow<Q>,
@@ -145,15 +154,16 @@ impl Object {
         self.0.get_mut(key)
     }
 
+    /// Removes the entry for `key`, keeping the order of the rest.
     pub fn remove<Q: ?Sized>(&mut self, key: &Q) -> Option<Value>
     where
         RcString: Borrow<Q>,
         Q: Hash + Eq,
     {
-        self.0.remove(key)
+        self.0.shift_remove(key)
     }
 
-    pub fn entry(&mut self, key: RcString) -> std::collections::hash_map::Entry<RcString, Value> {
+    pub fn entry(&mut self, key: RcString) -> indexmap::map::Entry<RcString, Value> {
         self.0.entry(key)
     }
 }
@@ -273,12 +283,46 @@ impl Serialize for Value {
     where
         S: Serializer,
     {
+        self.serialize_with(serializer, false)
+    }
+}
+
+/// A value that is serialized with numbers written in the form of the literals they were read
+/// from, as jq does.
+///
+/// This relies on `arbitrary_precision` of serde_json, so this should only be given to a
+/// serializer of serde_json. Use the [Serialize] impl of [Value] itself for other formats.
+/// Without the `arbitrary-precision` feature, this is the same as the [Serialize] impl of [Value].
+pub struct LiteralPreservingJson<'a>(pub &'a Value);
+
+impl Serialize for LiteralPreservingJson<'_> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        self.0.serialize_with(serializer, true)
+    }
+}
+
+impl Value {
+    fn serialize_with<S>(&self, serializer: S, preserve_literals: bool) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let wrap = |value| SerializeWith {
+            value,
+            preserve_literals,
+        };
         match self {
             Value::Null => serializer.serialize_none(),
             Value::Boolean(v) => serializer.serialize_bool(*v),
             Value::Number(v) => {
                 if v.is_finite() {
-                    v.serialize(serializer)
+                    if preserve_literals {
+                        v.serialize_as_literal(serializer)
+                    } else {
+                        v.serialize(serializer)
+                    }
                 } else if v.is_nan() {
                     serializer.serialize_none()
                 } else if v.is_sign_negative() {
@@ -291,14 +335,14 @@ impl Serialize for Value {
             Value::Array(arr) => {
                 let mut seq = serializer.serialize_seq(Some(arr.len()))?;
                 for e in arr.as_ref() {
-                    seq.serialize_element::<Value>(e)?;
+                    seq.serialize_element(&wrap(e))?;
                 }
                 seq.end()
             }
             Value::Object(obj) => {
                 let mut map = serializer.serialize_map(Some(obj.len()))?;
                 for (k, v) in obj.as_ref() {
-                    map.serialize_entry::<String, Value>(k, v)?;
+                    map.serialize_entry::<String, _>(k, &wrap(v))?;
                 }
                 map.end()
             }
@@ -306,6 +350,21 @@ impl Serialize for Value {
     }
 }
 
+struct SerializeWith<'a> {
+    value: &'a Value,
+    preserve_literals: bool,
+}
+
+impl Serialize for SerializeWith<'_> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        self.value
+            .serialize_with(serializer, self.preserve_literals)
+    }
+}
+
 impl<'de> Deserialize<'de> for Value {
     fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
     where
@@ -413,11 +472,19 @@ impl<'de> Deserialize<'de> for Value {
             where
                 A: MapAccess<'de>,
             {
+                let first_key = match map.next_key::<FirstKey>()? {
+                    Some(FirstKey::NumberToken) => {
+                        return number_from_json_literal(map).map(Value::Number)
+                    }
+                    Some(FirstKey::Key(key)) => key,
+                    None => return Ok(Object::new().into()),
+                };
                 let mut obj = if let Some(n) = map.size_hint() {
-                    Object::with_capacity(n)
+                    Object::with_capacity(n + 1)
                 } else {
                     Object::new()
                 };
+                obj.insert(first_key, map.next_value::<Value>()?);
                 while let Some((key, value)) = map.next_entry::<String, Value>()? {
                     obj.insert(key, value);
                 }
@@ -428,6 +495,52 @@ impl<'de> Deserialize<'de> for Value {
     }
 }
 
+/// The first key of a map, which is [JSON_NUMBER_TOKEN] if the map is a number literal given by
+/// serde_json. The token is recognized without allocating a string for it.
+enum FirstKey {
+    NumberToken,
+    Key(String),
+}
+
+impl<'de> Deserialize<'de> for FirstKey {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct V;
+        impl<'de> Visitor<'de> for V {
+            type Value = FirstKey;
+
+            fn expecting(&self, formatter: &mut Formatter) -> std::fmt::Result {
+                write!(formatter, "a string")
+            }
+
+            fn visit_str<E>(self, v: &str) -> Result<Self::Value, E>
+            where
+                E: Error,
+            {
+                if cfg!(feature = "arbitrary-precision") && v == JSON_NUMBER_TOKEN {
+                    Ok(FirstKey::NumberToken)
+                } else {
+                    Ok(FirstKey::Key(v.to_string()))
+                }
+            }
+
+            fn visit_string<E>(self, v: String) -> Result<Self::Value, E>
+            where
+                E: Error,
+            {
+                if cfg!(feature = "arbitrary-precision") && v == JSON_NUMBER_TOKEN {
+                    Ok(FirstKey::NumberToken)
+                } else {
+                    Ok(FirstKey::Key(v))
+                }
+            }
+        }
+        deserializer.deserialize_string(V)
+    }
+}
+
 impl PartialOrd for Value {
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
         Some(Self::cmp(self, other))
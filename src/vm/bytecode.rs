@@ -1,7 +1,8 @@
 use std::fmt::{Debug, Formatter};
 
 use crate::{
-    vm::{Address, Result, ScopeId, ScopedSlot},
+    util::Rc,
+    vm::{machine::NativeClosure, Address, Result, ScopeId, ScopedSlot},
     Value,
 };
 
@@ -17,6 +18,7 @@ pub(crate) struct ClosureAddress(pub(crate) Address);
 pub type NamedFn0 = NamedFunction<fn(Value) -> Result<Value>>;
 pub type NamedFn1 = NamedFunction<fn(Value, Value) -> Result<Value>>;
 pub type NamedFn2 = NamedFunction<fn(Value, Value, Value) -> Result<Value>>;
+pub type NamedFn3 = NamedFunction<fn(Value, Value, Value, Value) -> Result<Value>>;
 
 impl<F: Clone> Debug for NamedFunction<F> {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
@@ -24,6 +26,74 @@ impl<F: Clone> Debug for NamedFunction<F> {
     }
 }
 
+/// The signature of a function registered by the embedder,
+/// which takes the context and the values of the args.
+#[cfg(not(feature = "sync"))]
+pub type NativeFn = dyn Fn(Value, &[Value]) -> Result<Value>;
+/// The signature of a function registered by the embedder,
+/// which takes the context and the values of the args.
+#[cfg(feature = "sync")]
+pub type NativeFn = dyn Fn(Value, &[Value]) -> Result<Value> + Send + Sync;
+
+/// A function registered by the embedder with
+/// [Compiler::register_fn](crate::compile::compiler::Compiler::register_fn).
+#[derive(Clone)]
+pub struct NativeFunction {
+    pub name: Rc<str>,
+    pub arity: usize,
+    pub func: Rc<NativeFn>,
+}
+
+impl PartialEq for NativeFunction {
+    fn eq(&self, other: &Self) -> bool {
+        self.name == other.name && self.arity == other.arity && Rc::ptr_eq(&self.func, &other.func)
+    }
+}
+
+impl Eq for NativeFunction {}
+
+impl Debug for NativeFunction {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_fmt(format_args!("Native {}/{}", self.name, self.arity))
+    }
+}
+
+/// A stream of values produced by a native generator.
+pub type ValueIterator = Box<dyn Iterator<Item = Result<Value>>>;
+
+/// The signature of a generator registered by the embedder,
+/// which takes the context and the closures of the args.
+#[cfg(not(feature = "sync"))]
+pub type NativeGeneratorFn = dyn Fn(Value, Vec<NativeClosure>) -> Result<ValueIterator>;
+/// The signature of a generator registered by the embedder,
+/// which takes the context and the closures of the args.
+#[cfg(feature = "sync")]
+pub type NativeGeneratorFn =
+    dyn Fn(Value, Vec<NativeClosure>) -> Result<ValueIterator> + Send + Sync;
+
+/// A generator registered by the embedder with
+/// [Compiler::register_generator](crate::compile::compiler::Compiler::register_generator).
+#[derive(Clone)]
+pub struct NativeGenerator {
+    pub name: Rc<str>,
+    pub arity: usize,
+    pub func: Rc<NativeGeneratorFn>,
+}
+
+impl PartialEq for NativeGenerator {
+    fn eq(&self, other: &Self) -> bool {
+        self.name == other.name && self.arity == other.arity && Rc::ptr_eq(&self.func, &other.func)
+    }
+}
+
+impl Eq for NativeGenerator {}
+
+impl Debug for NativeGenerator {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_fmt(format_args!("NativeGenerator {}/{}", self.name, self.arity))
+    }
+}
+
 /// Byte code of the virtual machine.
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub(crate) enum ByteCode {
@@ -174,6 +244,15 @@ pub(crate) enum ByteCode {
     /// Pops a value from the stack
     /// If there's no more input, produce a [QueryExecutionError::NoMoreInputError] instead.
     Input,
+    /// Pops a module path from the stack, loads the module with the module loader of the machine,
+    /// and pushes its metadata as described in [crate::module_loader::load_module_meta].
+    ModuleMeta,
+    /// Gives the value on the top of the stack to [crate::vm::machine::DebugSink::debug],
+    /// leaving it on the stack.
+    Debug,
+    /// Gives the value on the top of the stack to [crate::vm::machine::DebugSink::stderr],
+    /// leaving it on the stack.
+    Stderr,
 
     /// Pops a value `context` from the stack, invokes the function with the arg `context`, and pushes the resulting value to the stack.
     /// # Panics
@@ -189,11 +268,30 @@ pub(crate) enum ByteCode {
     /// # Panics
     /// Panics if the stack had less than 3 elements, or the invoked function panicked.
     Intrinsic2(NamedFn2),
+    /// Pops values `arg3`, `arg2` and `arg1` from the stack, and another value `context` from the stack,
+    /// and invokes the function with the arg `context, arg1, arg2, arg3`, and pushes the resulting value to the stack.
+    /// # Panics
+    /// Panics if the stack had less than 4 elements, or the invoked function panicked.
+    Intrinsic3(NamedFn3),
+    /// Pops `arity` values `argN`, ..., `arg1` from the stack, pops another value `context` from the stack,
+    /// and invokes the function with the arg `context, [arg1, ..., argN]`, and pushes the resulting value to the stack.
+    /// # Panics
+    /// Panics if the stack had less than `arity + 1` elements, or the invoked function panicked.
+    CallNative(NativeFunction),
+    /// Pops `arity` closures `closureN`, ..., `closure1` from the closure stack, pops a value `context` from the stack,
+    /// and invokes the generator with the arg `context, [closure1, ..., closureN]`.
+    /// Then pushes a fork that pushes each value the generator produced to the stack,
+    /// and backtracks.
+    /// # Panics
+    /// Panics if the stack was empty, the closure stack had less than `arity` elements,
+    /// or the invoked function panicked.
+    CallNativeGenerator(NativeGenerator),
 }
 
+/// A compiled program. Cloning is cheap since the byte codes are shared.
 #[derive(Debug, Clone)]
 pub struct Program {
-    pub(crate) code: Vec<ByteCode>,
+    pub(crate) code: Rc<[ByteCode]>,
     pub(crate) entry_point: Address,
 }
 
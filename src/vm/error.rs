@@ -1,8 +1,8 @@
-use std::rc::Rc;
-
 use thiserror::Error;
 
-use crate::{value::RcString, vm::machine::LabelId, Number, Value};
+use crate::{
+    module_loader::ModuleLoadError, util::Rc, value::RcString, vm::machine::LabelId, Number, Value,
+};
 
 pub type Result<T, E = QueryExecutionError> = std::result::Result<T, E>;
 
@@ -94,6 +94,25 @@ pub enum QueryExecutionError {
     InputError(#[from] InputError),
     #[error("There's no more input")]
     NoMoreInputError,
+    #[error(transparent)]
+    ModuleLoadError(#[from] ModuleLoadError),
+    #[error("Execution fuel was exhausted")]
+    OutOfFuel,
+    #[error("Execution was cancelled")]
+    Cancelled,
+    /// Raised by `halt` and `halt_error` to stop the execution, which can't be caught.
+    /// The `value` is the message given to `halt_error`, which is expected to be written to stderr.
+    #[error("Halted with exit code {exit_code}")]
+    Halt {
+        value: Option<Value>,
+        exit_code: i32,
+    },
+    #[error("Depth of function calls exceeded the limit {0}")]
+    FrameDepthLimitExceeded(usize),
+    #[error("Number of forks exceeded the limit {0}")]
+    ForkLimitExceeded(usize),
+    #[error("Size of a value exceeded the limit {0}")]
+    ValueSizeLimitExceeded(usize),
     #[error("{0:?}")]
     UserDefinedError(Value),
 }
@@ -1,7 +1,10 @@
 use std::{
-    cell::{RefCell, RefMut},
-    iter::Fuse,
-    rc::Rc,
+    cell::{Cell, RefCell, RefMut},
+    iter::{Empty, Fuse, Once},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
 };
 
 use itertools::Itertools;
@@ -16,13 +19,14 @@ use crate::{
         PStack, PVector,
     },
     intrinsic,
-    util::make_owned,
+    module_loader::{load_module_meta, ModuleLoadError, SharedModuleLoader},
+    util::{make_owned, MaybeSendSync, Rc},
     vm::{
-        bytecode::{ClosureAddress, NamedFunction},
+        bytecode::{ClosureAddress, NamedFunction, NativeFunction, NativeGenerator, ValueIterator},
         error::QueryExecutionError,
         Address, ByteCode, Program, Result, ScopeId, ScopedSlot, Value,
     },
-    Array, InputError,
+    Array, InputError, Object,
 };
 
 #[derive(Debug, Clone, Eq, PartialEq, Error)]
@@ -58,14 +62,8 @@ impl From<PathElement> for Value {
 
 #[derive(Debug, Clone)]
 enum PathValueIterator {
-    Array {
-        array: Rc<Array>,
-        next: usize,
-    },
-    Object {
-        sorted_elements: Rc<Vec<(Rc<String>, Value)>>,
-        next: usize,
-    },
+    Array { array: Rc<Array>, next: usize },
+    Object { object: Rc<Object>, next: usize },
 }
 
 impl Iterator for PathValueIterator {
@@ -82,17 +80,10 @@ impl Iterator for PathValueIterator {
                     Some(ret)
                 }
             }
-            PathValueIterator::Object {
-                sorted_elements,
-                next,
-            } => {
-                if *next >= sorted_elements.len() {
-                    None
-                } else {
-                    let (index, value) = sorted_elements[*next].clone();
-                    *next += 1;
-                    Some((PathElement::Object(index), value))
-                }
+            PathValueIterator::Object { object, next } => {
+                let (key, value) = object.get_index(*next)?;
+                *next += 1;
+                Some((PathElement::Object(key.clone()), value.clone()))
             }
         }
     }
@@ -100,42 +91,227 @@ impl Iterator for PathValueIterator {
 
 type Frames = PVector<Option<Frame>>;
 type Closure = (ClosureAddress, Frames);
+/// The remaining fuel of an execution, shared with the runs of the closures given to native
+/// generators in it so that they consume the same fuel.
+type Fuel = std::rc::Rc<Cell<Option<u64>>>;
+
+/// A closure given to a native generator as an arg.
+#[derive(Clone)]
+pub struct NativeClosure {
+    machine: Machine,
+    closure: Closure,
+    fuel: Fuel,
+    /// The frame depth and the number of forks of the execution that called the native generator,
+    /// which count toward the [Limits] of the run of the closure.
+    base_frame_depth: usize,
+    base_forks: usize,
+}
+
+/// Results of running a [NativeClosure].
+pub type NativeClosureResultIterator =
+    ResultIterator<Once<Result<Value, InputError>>, Empty<Result<Value, InputError>>>;
+
+impl std::fmt::Debug for NativeClosure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("NativeClosure")
+            .field(&self.closure.0)
+            .finish()
+    }
+}
+
+impl NativeClosure {
+    /// Runs the closure on the `context`, and returns the values it produced.
+    /// Note that `input` in the closure doesn't see any input.
+    pub fn run(&self, context: Value) -> NativeClosureResultIterator {
+        let (ClosureAddress(address), frames) = self.closure.clone();
+        let mut state = State::new(address.get_next());
+        match self.machine.program.fetch_code(address) {
+            Some(ByteCode::NewFrame {
+                id,
+                variable_cnt,
+                closure_cnt,
+                label_cnt,
+            }) => {
+                // The first code of a program is always `Output`, so returning from the closure
+                // produces the value.
+                state.push_frame(
+                    Some(frames),
+                    *id,
+                    *variable_cnt,
+                    *closure_cnt,
+                    *label_cnt,
+                    false,
+                    Address(0),
+                );
+            }
+            code => panic!("Expected a closure to start with NewFrame but was {code:?}"),
+        }
+        let mut env = Environment::new(state.save());
+        env.base_frame_depth = self.base_frame_depth;
+        env.base_forks = self.base_forks;
+        ResultIterator {
+            machine: self.machine.clone(),
+            fuel: self.fuel.clone(),
+            suspended: false,
+            finished: false,
+            env,
+            state,
+            context: std::iter::once(Ok(context)).fuse(),
+            input: std::iter::empty().fuse(),
+        }
+    }
+}
+
+/// The stream of values produced by a native generator, shared among forks.
+#[derive(Clone)]
+struct NativeIterator(std::rc::Rc<RefCell<ValueIterator>>);
+
+impl std::fmt::Debug for NativeIterator {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("NativeIterator")
+    }
+}
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub struct LabelId(usize);
 
 #[derive(Debug, Clone)]
 struct Frame {
-    slots: Rc<RefCell<Vec<Option<Value>>>>,
-    closure_slots: Rc<RefCell<Vec<Option<Closure>>>>,
-    label_slots: Rc<RefCell<Vec<Option<LabelId>>>>,
+    slots: std::rc::Rc<RefCell<Vec<Option<Value>>>>,
+    closure_slots: std::rc::Rc<RefCell<Vec<Option<Closure>>>>,
+    label_slots: std::rc::Rc<RefCell<Vec<Option<LabelId>>>>,
 }
 
 impl Frame {
     fn new(variable_cnt: usize, closure_cnt: usize, label_cnt: usize) -> Self {
         Self {
-            slots: Rc::new(RefCell::new(
+            slots: std::rc::Rc::new(RefCell::new(
                 std::iter::repeat(None).take(variable_cnt).collect(),
             )),
-            closure_slots: Rc::new(RefCell::new(
+            closure_slots: std::rc::Rc::new(RefCell::new(
                 std::iter::repeat(None).take(closure_cnt).collect(),
             )),
-            label_slots: Rc::new(RefCell::new(
+            label_slots: std::rc::Rc::new(RefCell::new(
                 std::iter::repeat(None).take(label_cnt).collect(),
             )),
         }
     }
 }
 
-#[derive(Debug)]
+/// Limits on resources used by an execution, where `None` means unlimited.
+/// Exceeding them produces an error that can be caught by `try`. Frames and forks of closures run
+/// by native generators are counted together with those of the execution that calls them.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Limits {
+    /// The maximum depth of nested function calls.
+    pub max_frame_depth: Option<usize>,
+    /// The maximum number of forks, i.e. points to backtrack to.
+    pub max_forks: Option<usize>,
+    /// The maximum size of constructed values, measured as the length of a string in bytes,
+    /// or the number of elements of an array or an object. Nested values are not counted.
+    pub max_value_size: Option<usize>,
+}
+
+impl Limits {
+    fn check_state(&self, state: &State, env: &Environment) -> Result<()> {
+        match (self.max_frame_depth, self.max_forks) {
+            (Some(limit), _) if env.frame_depth(state) > limit => {
+                Err(QueryExecutionError::FrameDepthLimitExceeded(limit))
+            }
+            (_, Some(limit)) if env.fork_count() > limit => {
+                Err(QueryExecutionError::ForkLimitExceeded(limit))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn check_value_size(&self, size: usize) -> Result<()> {
+        match self.max_value_size {
+            Some(limit) if size > limit => Err(QueryExecutionError::ValueSizeLimitExceeded(limit)),
+            _ => Ok(()),
+        }
+    }
+
+    fn check_value(&self, value: Value) -> Result<Value> {
+        let size = match &value {
+            Value::Null | Value::Boolean(_) | Value::Number(_) => 0,
+            Value::String(s) => s.len(),
+            Value::Array(arr) => arr.len(),
+            Value::Object(obj) => obj.len(),
+        };
+        self.check_value_size(size)?;
+        Ok(value)
+    }
+}
+
+/// A token to cancel running executions from anywhere, e.g. another thread.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Cancels all executions that watch this token.
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// Receives values emitted by `debug` and `stderr` while running queries.
+pub trait DebugSink: MaybeSendSync {
+    fn debug(&self, value: &Value);
+    fn stderr(&self, value: &Value);
+}
+
+/// Writes values given to `debug` and `stderr` to the [log] crate, which is the default sink.
+#[derive(Debug, Clone, Default)]
+pub struct LogDebugSink;
+
+impl DebugSink for LogDebugSink {
+    fn debug(&self, value: &Value) {
+        log::debug!("[\"DEBUG:\",{value:?}]");
+    }
+
+    fn stderr(&self, value: &Value) {
+        log::info!("{value:?}");
+    }
+}
+
+#[derive(Clone)]
 pub struct Machine {
     program: Rc<Program>,
+    module_loader: Option<Rc<dyn SharedModuleLoader>>,
+    fuel: Option<u64>,
+    limits: Limits,
+    cancellation_token: Option<CancellationToken>,
+    debug_sink: Rc<dyn DebugSink>,
+}
+
+impl std::fmt::Debug for Machine {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Machine")
+            .field("program", &self.program)
+            .field("fuel", &self.fuel)
+            .field("limits", &self.limits)
+            .field("cancellation_token", &self.cancellation_token)
+            .finish_non_exhaustive()
+    }
 }
 
 #[derive(Debug)]
 struct Environment {
     next_label_id: usize,
     forks: Vec<(<State as Undo>::UndoToken, OnFork)>,
+    /// The frame depth and the number of forks of the execution this is nested in, if this runs
+    /// a [NativeClosure].
+    base_frame_depth: usize,
+    base_forks: usize,
 }
 
 #[derive(Debug)]
@@ -150,6 +326,7 @@ struct State {
     paths: UStack<Option<(Value, Value, PStack<PathElement>)>>, // origin, current, path stack
 
     iterators: UStack<PathValueIterator>,
+    native_iterators: UStack<NativeIterator>,
 }
 
 #[derive(Debug, Clone)]
@@ -162,6 +339,9 @@ enum OnFork {
     CatchLabel(LabelId),
     Iterate,
     IterateContext,
+    IterateNative,
+    /// Resumes the execution that was suspended since the fuel was exhausted.
+    Resume,
 }
 
 impl Environment {
@@ -169,9 +349,19 @@ impl Environment {
         Self {
             next_label_id: 0,
             forks: vec![(state, OnFork::IterateContext)],
+            base_frame_depth: 0,
+            base_forks: 0,
         }
     }
 
+    fn frame_depth(&self, state: &State) -> usize {
+        self.base_frame_depth + state.frame_stack.len()
+    }
+
+    fn fork_count(&self) -> usize {
+        self.base_forks + self.forks.len()
+    }
+
     fn push_fork(&mut self, state: &mut State, on_fork: OnFork, mut new_pc: Address) {
         std::mem::swap(&mut state.pc, &mut new_pc);
         let token = state.save();
@@ -200,6 +390,7 @@ impl State {
             frame_stack: Default::default(),
             closure_stack: Default::default(),
             iterators: Default::default(),
+            native_iterators: Default::default(),
         }
     }
 
@@ -294,6 +485,15 @@ impl State {
         self.iterators.top_mut()
     }
 
+    fn push_native_iterator(&mut self, iter: ValueIterator) {
+        self.native_iterators
+            .push(NativeIterator(std::rc::Rc::new(RefCell::new(iter))))
+    }
+
+    fn top_native_iterator(&mut self) -> Option<&NativeIterator> {
+        self.native_iterators.top()
+    }
+
     fn slot(&mut self, scoped_slot: &ScopedSlot) -> RefMut<Option<Value>> {
         let frame = self
             .frames
@@ -398,6 +598,7 @@ struct StateToken {
     paths: UStackToken,
 
     iterators: UStackToken,
+    native_iterators: UStackToken,
 }
 
 impl Undo for State {
@@ -412,6 +613,7 @@ impl Undo for State {
             closure_stack: self.closure_stack.save(),
             paths: self.paths.save(),
             iterators: self.iterators.save(),
+            native_iterators: self.native_iterators.save(),
         }
     }
 
@@ -423,28 +625,72 @@ impl Undo for State {
         self.closure_stack.undo(token.closure_stack);
         self.paths.undo(token.paths);
         self.iterators.undo(token.iterators);
+        self.native_iterators.undo(token.native_iterators);
     }
 }
 
 impl Machine {
-    pub fn new(program: Program) -> Self {
+    pub fn new<P: Into<Rc<Program>>>(program: P) -> Self {
         Self {
-            program: Rc::new(program),
+            program: program.into(),
+            module_loader: None,
+            fuel: None,
+            limits: Limits::default(),
+            cancellation_token: None,
+            debug_sink: Rc::new(LogDebugSink),
         }
     }
 
+    /// Sets where values given to `debug` and `stderr` go, which defaults to [LogDebugSink].
+    pub fn with_debug_sink(mut self, debug_sink: Rc<dyn DebugSink>) -> Self {
+        self.debug_sink = debug_sink;
+        self
+    }
+
+    /// Makes each run of the program watch `token`, and end with [QueryExecutionError::Cancelled]
+    /// once it was cancelled.
+    pub fn with_cancellation_token(mut self, token: CancellationToken) -> Self {
+        self.cancellation_token = Some(token);
+        self
+    }
+
+    /// Limits resources used by each run of the program.
+    pub fn with_limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Limits the number of byte codes executed by each run of the program to `fuel`.
+    /// Once exhausted, the [ResultIterator] produces [QueryExecutionError::OutOfFuel] and suspends,
+    /// which can be resumed with [ResultIterator::add_fuel].
+    /// Closures run by native generators consume the same fuel, and running out of it there ends
+    /// the execution without a way to resume it.
+    pub fn with_fuel(mut self, fuel: u64) -> Self {
+        self.fuel = Some(fuel);
+        self
+    }
+
+    /// Sets the module loader used by `modulemeta` to load modules while running the program.
+    pub fn with_module_loader(mut self, module_loader: Rc<dyn SharedModuleLoader>) -> Self {
+        self.module_loader = Some(module_loader);
+        self
+    }
+
     pub fn start<
         C: Iterator<Item = Result<Value, InputError>>,
         I: Iterator<Item = Result<Value, InputError>>,
     >(
-        &mut self,
+        &self,
         context: C,
         input: I,
     ) -> ResultIterator<C, I> {
         let mut state = State::new(self.program.entry_point);
         let env = Environment::new(state.save());
         ResultIterator {
-            program: self.program.clone(),
+            machine: self.clone(),
+            fuel: std::rc::Rc::new(Cell::new(self.fuel)),
+            suspended: false,
+            finished: false,
             env,
             state,
             context: context.fuse(),
@@ -457,7 +703,13 @@ pub struct ResultIterator<
     C: Iterator<Item = Result<Value, InputError>>,
     I: Iterator<Item = Result<Value, InputError>>,
 > {
-    program: Rc<Program>,
+    machine: Machine,
+    /// The remaining fuel.
+    fuel: Fuel,
+    /// Whether the execution was suspended since the fuel was exhausted.
+    suspended: bool,
+    /// Whether the execution was cancelled or halted, which can't be resumed.
+    finished: bool,
     env: Environment,
     state: State,
     context: Fuse<C>,
@@ -472,18 +724,69 @@ impl<
     type Item = Result<Value>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        run_code(
-            &self.program,
+        if self.suspended || self.finished {
+            return None;
+        }
+        let ret = run_code(
+            &self.machine,
+            &self.fuel,
             &mut self.state,
             &mut self.env,
             &mut self.context,
             &mut self.input,
-        )
+        );
+        self.suspended = matches!(ret, Some(Err(QueryExecutionError::OutOfFuel)));
+        self.finished = matches!(
+            ret,
+            Some(Err(
+                QueryExecutionError::Cancelled | QueryExecutionError::Halt { .. }
+            ))
+        );
+        ret
+    }
+}
+
+impl<
+        C: Iterator<Item = Result<Value, InputError>>,
+        I: Iterator<Item = Result<Value, InputError>>,
+    > ResultIterator<C, I>
+{
+    /// The remaining fuel, or `None` if the fuel is not limited.
+    pub fn fuel(&self) -> Option<u64> {
+        self.fuel.get()
+    }
+
+    /// Adds fuel, and resumes the execution if it was suspended by [QueryExecutionError::OutOfFuel].
+    /// To abort the execution instead, just drop this iterator.
+    pub fn add_fuel(&mut self, fuel: u64) {
+        self.fuel.set(Some(
+            self.fuel.get().map_or(fuel, |f| f.saturating_add(fuel)),
+        ));
+        self.suspended = false;
     }
 }
 
+/// Whether the error from a native generator ends the execution instead of being caught.
+fn aborts_native(e: &QueryExecutionError) -> bool {
+    matches!(
+        e,
+        QueryExecutionError::OutOfFuel
+            | QueryExecutionError::Cancelled
+            | QueryExecutionError::Halt { .. }
+    )
+}
+
+/// Ends the execution with an error from a native generator, which is from a closure run by it
+/// that ran out of fuel, was cancelled or halted. Running out of fuel can't be resumed, as the
+/// native generator can't be suspended in the middle.
+fn abort_native(env: &mut Environment, e: QueryExecutionError) -> Result<Value> {
+    env.forks.clear();
+    Err(e)
+}
+
 fn run_code(
-    program: &Program,
+    machine: &Machine,
+    fuel: &Fuel,
     state: &mut State,
     env: &mut Environment,
     context: &mut impl Iterator<Item = Result<Value, InputError>>,
@@ -492,6 +795,9 @@ fn run_code(
     let mut err: Option<QueryExecutionError> = None;
     log::trace!("Start from environment {:?}", env);
     'backtrack: loop {
+        if let Some(QueryExecutionError::Halt { .. }) = err {
+            return err.map(Err);
+        }
         log::trace!(
             "Fork stack: {:?}",
             env.forks.iter().map(|(_, f)| f).collect_vec()
@@ -524,6 +830,7 @@ fn run_code(
                     continue 'select_fork;
                 }
                 (OnFork::Nop, None) => {} // nop
+                (OnFork::Resume, None) => {}
                 (OnFork::IgnoreError, _) => {
                     if catch_skip == 0 {
                         err = None;
@@ -572,6 +879,27 @@ fn run_code(
                         }
                     }
                 }
+                (OnFork::IterateNative, None) => {
+                    state.undo(token);
+                    let it = state
+                        .top_native_iterator()
+                        .expect("No native iterator to iterate on")
+                        .clone();
+                    let next = it.0.borrow_mut().next();
+                    match next {
+                        None => continue 'select_fork,
+                        Some(Err(e)) if aborts_native(&e) => return Some(abort_native(env, e)),
+                        Some(Err(e)) => {
+                            err = Some(e);
+                            continue 'select_fork;
+                        }
+                        Some(Ok(value)) => {
+                            env.push_fork(state, OnFork::IterateNative, state.pc);
+                            state.push(value);
+                            break 'select_fork state.save();
+                        }
+                    }
+                }
                 (OnFork::IterateContext, None) => {
                     match context.next() {
                         None => return None,
@@ -589,6 +917,8 @@ fn run_code(
                     }
                 }
                 (OnFork::IterateContext, Some(_e)) => {
+                    // Restore the state so that the next input starts from the beginning.
+                    state.undo(token);
                     env.push_fork(state, OnFork::IterateContext, state.pc);
                     return Some(Err(err.take().unwrap()));
                 }
@@ -606,7 +936,25 @@ fn run_code(
             if err.is_some() {
                 continue 'backtrack;
             }
-            let code = program.fetch_code(state.pc)?;
+            if let Some(token) = &machine.cancellation_token {
+                if token.is_cancelled() {
+                    return Some(Err(QueryExecutionError::Cancelled));
+                }
+            }
+            if let Some(remaining) = fuel.get() {
+                if remaining > 0 {
+                    fuel.set(Some(remaining - 1));
+                } else if call_pc.is_none() && context_frame.is_none() && !chain_ret {
+                    // Suspend only where we don't have any pending call so that we can resume here.
+                    env.push_fork(state, OnFork::Resume, state.pc);
+                    return Some(Err(QueryExecutionError::OutOfFuel));
+                }
+            }
+            if let Err(e) = machine.limits.check_state(state, env) {
+                err = Some(e);
+                continue 'backtrack;
+            }
+            let code = machine.program.fetch_code(state.pc)?;
             log::trace!(
                 "Execute code {:?} on stack = {:?}, slots = {:?}",
                 code,
@@ -663,6 +1011,10 @@ fn run_code(
                             Value::String(s) => {
                                 let mut map = make_owned(map);
                                 map.insert(s, value);
+                                if let Err(e) = machine.limits.check_value_size(map.len()) {
+                                    err = Some(e);
+                                    continue 'backtrack;
+                                }
                                 state.push(map.into());
                             }
                             value => {
@@ -683,6 +1035,9 @@ fn run_code(
                     match slot_item {
                         Value::Array(v) => {
                             Rc::make_mut(v).push(value);
+                            if let Err(e) = machine.limits.check_value_size(v.len()) {
+                                err = Some(e);
+                            }
                         }
                         _ => {
                             panic!("expected an array to append to, but was not an array");
@@ -737,17 +1092,7 @@ fn run_code(
                             continue 'backtrack;
                         }
                         Value::Array(array) => PathValueIterator::Array { array, next: 0 },
-                        Value::Object(map) => {
-                            let sorted_elements = map
-                                .iter()
-                                .map(|(k, v)| (k.clone(), v.clone())) // TODO: Is there a better way?
-                                .sorted_unstable_by(|(lhs, _), (rhs, _)| Ord::cmp(lhs, rhs))
-                                .collect();
-                            PathValueIterator::Object {
-                                sorted_elements: Rc::new(sorted_elements),
-                                next: 0,
-                            }
-                        }
+                        Value::Object(object) => PathValueIterator::Object { object, next: 0 },
                     };
                     state.push_iterator(iter);
                     env.push_fork(state, OnFork::Iterate, state.pc.get_next());
@@ -927,10 +1272,36 @@ fn run_code(
                     }
                     None => err = Some(QueryExecutionError::NoMoreInputError),
                 },
+                ModuleMeta => {
+                    let context = state.pop();
+                    let result = match (&context, machine.module_loader.as_deref()) {
+                        (Value::String(path), Some(module_loader)) => {
+                            load_module_meta(module_loader, path).map_err(Into::into)
+                        }
+                        (Value::String(path), None) => {
+                            Err(ModuleLoadError::NoModuleLoaderError(path.to_string()).into())
+                        }
+                        _ => Err(QueryExecutionError::InvalidArgType("modulemeta", context)),
+                    };
+                    match result {
+                        Ok(value) => state.push(value),
+                        Err(e) => err = Some(e),
+                    }
+                }
+                Debug => {
+                    let value = state.pop();
+                    machine.debug_sink.debug(&value);
+                    state.push(value);
+                }
+                Stderr => {
+                    let value = state.pop();
+                    machine.debug_sink.stderr(&value);
+                    state.push(value);
+                }
                 Intrinsic0(NamedFunction { name, func }) => {
                     let context = state.pop();
                     log::trace!("Calling function {} with context {:?}", name, context);
-                    match func(context) {
+                    match func(context).and_then(|v| machine.limits.check_value(v)) {
                         Ok(value) => state.push(value),
                         Err(QueryExecutionError::UserDefinedError(Value::Null)) => {
                             continue 'backtrack
@@ -947,7 +1318,7 @@ fn run_code(
                         context,
                         arg1
                     );
-                    match func(context, arg1) {
+                    match func(context, arg1).and_then(|v| machine.limits.check_value(v)) {
                         Ok(value) => state.push(value),
                         Err(QueryExecutionError::UserDefinedError(Value::Null)) => {
                             continue 'backtrack
@@ -966,11 +1337,76 @@ fn run_code(
                         arg1,
                         arg2
                     );
-                    match func(context, arg1, arg2) {
+                    match func(context, arg1, arg2).and_then(|v| machine.limits.check_value(v)) {
                         Ok(value) => state.push(value),
                         Err(e) => err = Some(e),
                     }
                 }
+                Intrinsic3(NamedFunction { name, func }) => {
+                    let arg3 = state.pop();
+                    let arg2 = state.pop();
+                    let arg1 = state.pop();
+                    let context = state.pop();
+                    log::trace!(
+                        "Calling function {} with context {:?} and arg1 {:?} and arg2 {:?} and arg3 {:?}",
+                        name,
+                        context,
+                        arg1,
+                        arg2,
+                        arg3
+                    );
+                    match func(context, arg1, arg2, arg3)
+                        .and_then(|v| machine.limits.check_value(v))
+                    {
+                        Ok(value) => state.push(value),
+                        Err(e) => err = Some(e),
+                    }
+                }
+                CallNativeGenerator(NativeGenerator { name, arity, func }) => {
+                    let mut closures: Vec<_> = (0..*arity)
+                        .map(|_| NativeClosure {
+                            machine: machine.clone(),
+                            closure: state.pop_closure(),
+                            fuel: fuel.clone(),
+                            base_frame_depth: env.frame_depth(state),
+                            base_forks: env.fork_count(),
+                        })
+                        .collect();
+                    closures.reverse();
+                    let context = state.pop();
+                    log::trace!(
+                        "Calling native generator {} with context {:?}",
+                        name,
+                        context
+                    );
+                    match func(context, closures) {
+                        Ok(iter) => {
+                            state.push_native_iterator(iter);
+                            env.push_fork(state, OnFork::IterateNative, state.pc.get_next());
+                            continue 'backtrack;
+                        }
+                        Err(e) if aborts_native(&e) => return Some(abort_native(env, e)),
+                        Err(e) => err = Some(e),
+                    }
+                }
+                CallNative(NativeFunction { name, arity, func }) => {
+                    let mut args: Vec<_> = (0..*arity).map(|_| state.pop()).collect();
+                    args.reverse();
+                    let context = state.pop();
+                    log::trace!(
+                        "Calling native function {} with context {:?} and args {:?}",
+                        name,
+                        context,
+                        args
+                    );
+                    match func(context, &args).and_then(|v| machine.limits.check_value(v)) {
+                        Ok(value) => state.push(value),
+                        Err(QueryExecutionError::UserDefinedError(Value::Null)) => {
+                            continue 'backtrack
+                        }
+                        Err(e) => err = Some(e),
+                    }
+                }
             }
             state.pc.next();
         }
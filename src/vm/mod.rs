@@ -4,7 +4,8 @@ pub mod bytecode;
 pub mod error;
 pub mod machine;
 
-pub(crate) use bytecode::{ByteCode, Program};
+pub(crate) use bytecode::ByteCode;
+pub use bytecode::Program;
 pub use error::{QueryExecutionError, Result};
 
 use crate::Value;
@@ -1,6 +1,13 @@
 use std::error::Error;
 
-use xq::{module_loader::PreludeLoader, run_query, util::SharedIterator, InputError, Value};
+use xq::{
+    compile::compiler::Compiler,
+    module_loader::{PreludeLoader, SharedModuleLoader},
+    run_query,
+    util::SharedIterator,
+    vm::{machine::Limits, QueryExecutionError},
+    CompiledQuery, InputError, Value,
+};
 
 #[macro_export]
 macro_rules! test {
@@ -13,6 +20,15 @@ macro_rules! test {
 }
 
 pub(crate) fn run_test(query: &str, input: &str, output: &str) -> Result<(), Box<dyn Error>> {
+    run_test_with_module_loader(query, input, output, &PreludeLoader())
+}
+
+pub(crate) fn run_test_with_module_loader<M: SharedModuleLoader + Clone + 'static>(
+    query: &str,
+    input: &str,
+    output: &str,
+    module_loader: &M,
+) -> Result<(), Box<dyn Error>> {
     let input: SharedIterator<_> = serde_json::de::Deserializer::from_str(input)
         .into_iter::<Value>()
         .map(|r| r.map_err(InputError::new))
@@ -21,7 +37,9 @@ pub(crate) fn run_test(query: &str, input: &str, output: &str) -> Result<(), Box
     let expected: Vec<_> = serde_json::de::Deserializer::from_str(output)
         .into_iter::<Value>()
         .collect::<Result<_, serde_json::Error>>()?;
-    let output = run_query(query, input.clone(), input, &PreludeLoader())?
+    let output = CompiledQuery::compile(query, module_loader)?
+        .with_module_loader(module_loader.clone())
+        .run(input.clone(), input)
         .collect::<Result<Vec<Value>, _>>()?;
     if expected != output {
         eprintln!("{expected:?} {output:?}");
@@ -49,3 +67,23 @@ pub(crate) fn test_no_panic(query: &str, input: &str) -> Result<(), Box<dyn std:
     run_query(query, input.clone(), input, &PreludeLoader())?.for_each(drop);
     Ok(())
 }
+
+pub(crate) fn values(json: &str) -> Vec<Value> {
+    serde_json::de::Deserializer::from_str(json)
+        .into_iter::<Value>()
+        .collect::<Result<_, _>>()
+        .unwrap()
+}
+
+/// Runs the query compiled with the compiler against `null` under the limits.
+pub(crate) fn run_with_limits(
+    compiler: Compiler,
+    query: &str,
+    limits: Limits,
+) -> Vec<Result<Value, QueryExecutionError>> {
+    let query = CompiledQuery::compile_with(query, &PreludeLoader(), compiler)
+        .unwrap()
+        .with_limits(limits);
+    let input = values("null").into_iter().map(Ok::<_, InputError>);
+    query.run(input, std::iter::empty()).collect()
+}
@@ -0,0 +1,434 @@
+use std::path::Path;
+
+use xq::{
+    compile::compiler::Compiler,
+    module_loader::{
+        LoadedModule, ModuleLoadError, ModuleLoader, PreludeLoader, Result as LoadResult,
+    },
+    run_query,
+    vm::{
+        machine::{CancellationToken, DebugSink, Limits},
+        QueryExecutionError,
+    },
+    CompiledQuery, InputError, Object, Value,
+};
+
+use crate::common::{run_with_limits, values};
+
+fn run(query: &CompiledQuery, input: &str) -> Vec<Value> {
+    let input = values(input).into_iter().map(Ok::<_, InputError>);
+    query
+        .run(input, std::iter::empty())
+        .collect::<Result<_, _>>()
+        .unwrap()
+}
+
+#[test]
+fn run_compiled_query_many_times() {
+    let query = CompiledQuery::compile(".x + 1", &PreludeLoader()).unwrap();
+    assert_eq!(run(&query, r#"{"x": 1}"#), values("2"));
+    assert_eq!(run(&query, r#"{"x": 2} {"x": 3}"#), values("3 4"));
+}
+
+#[test]
+fn run_cloned_compiled_query() {
+    let query = CompiledQuery::compile("[., input]", &PreludeLoader()).unwrap();
+    let cloned = query.clone();
+    for query in [query, cloned] {
+        let context = values("1 2").into_iter().map(Ok::<_, InputError>);
+        let input = values("3 4").into_iter().map(Ok::<_, InputError>);
+        let output: Vec<_> = query.run(context, input).collect::<Result<_, _>>().unwrap();
+        assert_eq!(output, values("[1, 3] [2, 4]"));
+    }
+}
+
+#[test]
+fn run_program_from_compiler() {
+    let parsed = xq_lang::parse_program("map(. * 2)").unwrap();
+    let program = Compiler::new().compile(&parsed, &PreludeLoader()).unwrap();
+    let query = CompiledQuery::new(program);
+    assert_eq!(run(&query, "[1, 2]"), values("[2, 4]"));
+    assert_eq!(run(&query, "[]"), values("[]"));
+}
+
+/// A module loader that is neither `Clone` nor `'static`, which is enough to compile a query.
+struct BorrowingLoader<'a>(&'a PreludeLoader);
+
+impl ModuleLoader for BorrowingLoader<'_> {
+    fn prelude(&self) -> LoadResult<Vec<xq_lang::ast::Program>> {
+        self.0.prelude()
+    }
+
+    fn load_values(
+        &self,
+        path: &str,
+        search: Option<Vec<String>>,
+        importer: Option<&Path>,
+    ) -> LoadResult<Vec<Value>> {
+        self.0.load_values(path, search, importer)
+    }
+
+    fn load_program(
+        &self,
+        path: &str,
+        search: Option<Vec<String>>,
+        importer: Option<&Path>,
+    ) -> LoadResult<LoadedModule> {
+        self.0.load_program(path, search, importer)
+    }
+}
+
+#[test]
+fn run_query_with_borrowing_module_loader() {
+    let prelude = PreludeLoader();
+    let loader = BorrowingLoader(&prelude);
+    let input = values("[1, 2]").into_iter().map(Ok::<_, InputError>);
+    let output: Vec<_> = run_query("map(. + 1)", input, std::iter::empty(), &loader)
+        .unwrap()
+        .collect::<Result<_, _>>()
+        .unwrap();
+    assert_eq!(output, values("[2, 3]"));
+
+    let query = CompiledQuery::compile("add", &loader).unwrap();
+    assert_eq!(run(&query, "[1, 2]"), values("3"));
+}
+
+#[test]
+fn modulemeta_needs_module_loader() {
+    let query = CompiledQuery::compile(r#""a" | modulemeta"#, &PreludeLoader()).unwrap();
+    let results: Vec<_> = query
+        .run(std::iter::once(Ok(Value::Null)), std::iter::empty())
+        .collect();
+    assert!(matches!(
+        results[..],
+        [Err(QueryExecutionError::ModuleLoadError(
+            ModuleLoadError::NoModuleLoaderError(_)
+        ))]
+    ));
+
+    let results: Vec<_> = run_query(
+        r#""not_found" | modulemeta"#,
+        std::iter::once(Ok(Value::Null)),
+        std::iter::empty(),
+        &PreludeLoader(),
+    )
+    .unwrap()
+    .collect();
+    assert!(matches!(
+        results[..],
+        [Err(QueryExecutionError::ModuleLoadError(
+            ModuleLoadError::NotFoundError(_)
+        ))]
+    ));
+}
+
+#[cfg(feature = "sync")]
+#[test]
+fn run_compiled_query_on_threads() {
+    fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<CompiledQuery>();
+    assert_send_sync::<Value>();
+
+    let query = CompiledQuery::compile("[.[] * 2]", &PreludeLoader()).unwrap();
+    let handles: Vec<_> = (0..4)
+        .map(|i| {
+            let query = query.clone();
+            std::thread::spawn(move || run(&query, &format!("[{i}, {i}]")))
+        })
+        .collect();
+    let outputs: Vec<_> = handles
+        .into_iter()
+        .flat_map(|handle| handle.join().unwrap())
+        .collect();
+    assert_eq!(outputs, values("[0, 0] [2, 2] [4, 4] [6, 6]"));
+}
+
+#[test]
+fn run_out_of_fuel() {
+    let query = CompiledQuery::compile("repeat(.)", &PreludeLoader())
+        .unwrap()
+        .with_fuel(1000);
+    let input = values("1").into_iter().map(Ok::<_, InputError>);
+    let mut results = query.run(input, std::iter::empty());
+    let outputs: Vec<_> = results.by_ref().take_while(Result::is_ok).collect();
+    assert!(!outputs.is_empty());
+    assert_eq!(results.fuel(), Some(0));
+    assert!(results.next().is_none());
+}
+
+#[test]
+fn out_of_fuel_is_not_catchable() {
+    let query = CompiledQuery::compile("try last(range(1e18)) catch 0", &PreludeLoader())
+        .unwrap()
+        .with_fuel(1000);
+    let input = values("null").into_iter().map(Ok::<_, InputError>);
+    let mut results = query.run(input, std::iter::empty());
+    assert!(matches!(
+        results.next(),
+        Some(Err(QueryExecutionError::OutOfFuel))
+    ));
+    assert!(results.next().is_none());
+}
+
+#[test]
+fn resume_with_fuel() {
+    for query in [
+        "[range(100)] | add",
+        "def f: if . < 50 then . + 1 | f else . end; 0 | f",
+        "reduce (.[] | [limit(3; repeat(.))][]) as $x (0; . + $x)",
+    ] {
+        let expected = run(
+            &CompiledQuery::compile(query, &PreludeLoader()).unwrap(),
+            "[1,2,3]",
+        );
+        let query = CompiledQuery::compile(query, &PreludeLoader())
+            .unwrap()
+            .with_fuel(0);
+        let input = values("[1,2,3]").into_iter().map(Ok::<_, InputError>);
+        let mut results = query.run(input, std::iter::empty());
+        let mut outputs = vec![];
+        loop {
+            match results.next() {
+                Some(Ok(value)) => outputs.push(value),
+                Some(Err(QueryExecutionError::OutOfFuel)) => results.add_fuel(1),
+                Some(Err(e)) => panic!("{e:?}"),
+                None => break,
+            }
+        }
+        assert_eq!(outputs, expected);
+    }
+}
+
+#[test]
+fn frame_depth_limit() {
+    let limits = Limits {
+        max_frame_depth: Some(100),
+        ..Limits::default()
+    };
+    assert!(matches!(
+        run_with_limits(Compiler::new(), "def f: 1 + f; f", limits)[..],
+        [Err(QueryExecutionError::FrameDepthLimitExceeded(100))]
+    ));
+    let outputs = run_with_limits(
+        Compiler::new(),
+        "def f: if . < 10 then . + 1 | f else . end; 0 | f",
+        limits,
+    );
+    assert_eq!(
+        outputs.into_iter().collect::<Result<Vec<_>, _>>().unwrap(),
+        values("10")
+    );
+}
+
+#[test]
+fn fork_limit() {
+    let limits = Limits {
+        max_forks: Some(100),
+        ..Limits::default()
+    };
+    assert!(matches!(
+        run_with_limits(
+            Compiler::new(),
+            "def f(n): if n == 0 then n else (0, 1) as $x | f(n - 1) end; first(f(1000))",
+            limits
+        )[..],
+        [Err(QueryExecutionError::ForkLimitExceeded(100))]
+    ));
+    let outputs = run_with_limits(Compiler::new(), "[limit(10; repeat(1))] | add", limits);
+    assert_eq!(
+        outputs.into_iter().collect::<Result<Vec<_>, _>>().unwrap(),
+        values("10")
+    );
+}
+
+#[test]
+fn value_size_limit() {
+    let limits = Limits {
+        max_value_size: Some(100),
+        ..Limits::default()
+    };
+    for query in [
+        "[range(1000)]",
+        "reduce range(1000) as $i ({}; .[$i | tostring] = $i)",
+        r#""a" * 1000"#,
+        "[range(1000)] | length",
+    ] {
+        assert!(
+            matches!(
+                run_with_limits(Compiler::new(), query, limits)[..],
+                [Err(QueryExecutionError::ValueSizeLimitExceeded(100))]
+            ),
+            "{query}"
+        );
+    }
+    let outputs = run_with_limits(Compiler::new(), "[range(100)] | length", limits);
+    assert_eq!(
+        outputs.into_iter().collect::<Result<Vec<_>, _>>().unwrap(),
+        values("100")
+    );
+}
+
+#[test]
+fn limits_are_catchable() {
+    let limits = Limits {
+        max_frame_depth: Some(100),
+        max_forks: Some(1000),
+        max_value_size: Some(100),
+    };
+    let outputs = run_with_limits(
+        Compiler::new(),
+        r#"(try (def f: 1 + f; f) catch "depth"), (try [range(1000)] catch "size")"#,
+        limits,
+    );
+    assert_eq!(
+        outputs.into_iter().collect::<Result<Vec<_>, _>>().unwrap(),
+        values(r#""depth" "size""#)
+    );
+}
+
+#[test]
+fn cancel_from_another_thread() {
+    let token = CancellationToken::new();
+    let query = CompiledQuery::compile("try last(range(1e18)) catch 0", &PreludeLoader())
+        .unwrap()
+        .with_cancellation_token(token.clone());
+    let handle = std::thread::spawn(move || {
+        std::thread::sleep(std::time::Duration::from_millis(50));
+        token.cancel();
+    });
+    let input = values("null").into_iter().map(Ok::<_, InputError>);
+    let mut results = query.run(input, std::iter::empty());
+    assert!(matches!(
+        results.next(),
+        Some(Err(QueryExecutionError::Cancelled))
+    ));
+    assert!(results.next().is_none());
+    handle.join().unwrap();
+}
+
+#[test]
+fn cancel_between_outputs() {
+    let token = CancellationToken::new();
+    let query = CompiledQuery::compile("range(10)", &PreludeLoader())
+        .unwrap()
+        .with_cancellation_token(token.clone());
+    let input = values("null").into_iter().map(Ok::<_, InputError>);
+    let mut results = query.run(input, std::iter::empty());
+    assert_eq!(results.next().unwrap().unwrap(), Value::number(0));
+    token.cancel();
+    assert!(matches!(
+        results.next(),
+        Some(Err(QueryExecutionError::Cancelled))
+    ));
+    assert!(results.next().is_none());
+}
+
+#[test]
+fn compile_with_global_variables() {
+    let mut compiler = Compiler::new();
+    compiler.register_global("x", Value::number(1));
+    compiler.register_global("name", Value::string("xq".to_string()));
+    let query = CompiledQuery::compile_with(
+        "[$x, $name, ($x | . as $x | $x + 1), (def f: $x * 10; f)]",
+        &PreludeLoader(),
+        compiler,
+    )
+    .unwrap();
+    assert_eq!(run(&query, "null"), values(r#"[1, "xq", 2, 10]"#));
+}
+
+#[test]
+fn custom_environment() {
+    let mut environment = Object::new();
+    environment.insert("HOME".to_string(), Value::string("/home/xq".to_string()));
+    let mut compiler = Compiler::new();
+    compiler.set_environment(environment);
+    let query = CompiledQuery::compile_with(
+        "$ENV, env.HOME, (1 as $ENV | env | keys)",
+        &PreludeLoader(),
+        compiler,
+    )
+    .unwrap();
+    assert_eq!(
+        run(&query, "null"),
+        values(r#"{"HOME": "/home/xq"} "/home/xq" ["HOME"]"#)
+    );
+
+    let mut compiler = Compiler::new();
+    compiler.set_environment(Object::new());
+    let query = CompiledQuery::compile_with("$ENV, env", &PreludeLoader(), compiler).unwrap();
+    assert_eq!(run(&query, "null"), values("{} {}"));
+}
+
+#[test]
+fn halt_is_not_catchable() {
+    let query = CompiledQuery::compile(
+        r#"try (if . == 2 then "bye" | halt_error(3) else . end) catch 0"#,
+        &PreludeLoader(),
+    )
+    .unwrap();
+    let input = values("1 2 3").into_iter().map(Ok::<_, InputError>);
+    let mut results = query.run(input, std::iter::empty());
+    assert_eq!(results.next().unwrap().unwrap(), Value::number(1));
+    match results.next() {
+        Some(Err(QueryExecutionError::Halt {
+            value: Some(value),
+            exit_code: 3,
+        })) => assert_eq!(value, Value::string("bye".to_string())),
+        other => panic!("{other:?}"),
+    }
+    assert!(results.next().is_none());
+}
+
+#[derive(Clone, Default)]
+struct CollectingSink(std::sync::Arc<std::sync::Mutex<Vec<(&'static str, Value)>>>);
+
+impl DebugSink for CollectingSink {
+    fn debug(&self, value: &Value) {
+        self.0.lock().unwrap().push(("debug", value.clone()));
+    }
+
+    fn stderr(&self, value: &Value) {
+        self.0.lock().unwrap().push(("stderr", value.clone()));
+    }
+}
+
+#[test]
+fn debug_sink() {
+    let sink = CollectingSink::default();
+    let query = CompiledQuery::compile(
+        r#"debug | debug("x is \(.x)") | stderr | .x + 1"#,
+        &PreludeLoader(),
+    )
+    .unwrap()
+    .with_debug_sink(sink.clone());
+    assert_eq!(run(&query, r#"{"x": 1}"#), values("2"));
+    assert_eq!(
+        *sink.0.lock().unwrap(),
+        vec![
+            ("debug", values(r#"{"x": 1}"#).remove(0)),
+            ("debug", Value::string("x is 1".to_string())),
+            ("stderr", values(r#"{"x": 1}"#).remove(0)),
+        ]
+    );
+}
+
+#[test]
+fn error_does_not_affect_next_input() {
+    let query = CompiledQuery::compile(".a + 1", &PreludeLoader()).unwrap();
+    let input = values(r#"1 {"a": 1} 2 {"a": 2}"#)
+        .into_iter()
+        .map(Ok::<_, InputError>);
+    let results: Vec<_> = query.run(input, std::iter::empty()).collect();
+    assert!(matches!(
+        results[..],
+        [
+            Err(QueryExecutionError::IndexOnNonIndexable(_)),
+            Ok(_),
+            Err(QueryExecutionError::IndexOnNonIndexable(_)),
+            Ok(_),
+        ]
+    ));
+    let outputs: Vec<_> = results.into_iter().filter_map(Result::ok).collect();
+    assert_eq!(outputs, values("2 3"));
+}
@@ -75,3 +75,106 @@ test!(
     [0,0.346573590,0.804718956]
     "#
 );
+
+test!(
+    rounding_functions,
+    r#"
+    map(floor), map(ceil), map(round), map(trunc), map(nearbyint), map(fabs)
+    "#,
+    r#"
+    [-2.5,-1.2,0.5,1.5,1234567890123456789]
+    "#,
+    r#"
+    [-3,-2,0,1,1234567890123456789]
+    [-2,-1,1,2,1234567890123456789]
+    [-3,-1,1,2,1234567890123456789]
+    [-2,-1,0,1,1234567890123456789]
+    [-2,-1,0,2,1234567890123456789]
+    [2.5,1.2,0.5,1.5,1234567890123456789]
+    "#
+);
+
+test!(
+    exponential_and_logarithmic_functions,
+    r#"
+    map(exp2), map(exp10), (map(log2), map(log10), map(cbrt), map(exp | log) | map(. * 1000000000 | round / 1000000000))
+    "#,
+    r#"
+    [1,3,6]
+    "#,
+    r#"
+    [2,8,64]
+    [10,1000,1000000]
+    [0,1.584962501,2.584962501]
+    [0,0.477121255,0.77815125]
+    [1,1.44224957,1.817120593]
+    [1,3,6]
+    "#
+);
+
+test!(
+    float_decomposition_functions,
+    r#"
+    map(frexp), map(modf), map(logb | if isinfinite then "-inf" end), map(significand)
+    "#,
+    r#"
+    [8,-3.5,0]
+    "#,
+    r#"
+    [[0.5,4],[-0.875,2],[0,0]]
+    [[0,8],[-0.5,-3],[0,0]]
+    [3,1,"-inf"]
+    [1,-1.75,0]
+    "#
+);
+
+test!(
+    gamma_functions,
+    r#"
+    (map(tgamma), map(lgamma) | map(. * 1000000000 | round / 1000000000)), map(gamma == lgamma)
+    "#,
+    r#"
+    [1,5,0.5]
+    "#,
+    r#"
+    [1,24,1.772453851]
+    [0,3.17805383,0.572364943]
+    [true,true,true]
+    "#
+);
+
+test!(
+    two_argument_math_functions,
+    r#"
+    pow(2; 10), pow(.; 0.5), atan2(1; 1), fmin(1; 2), fmax(1; 2), ldexp(3; 2), scalb(3; 2), drem(10; 3), drem(11; 3)
+    "#,
+    r#"
+    4
+    "#,
+    r#"
+    1024
+    2
+    0.7853981633974483
+    1
+    2
+    12
+    12
+    1
+    -1
+    "#
+);
+
+test!(
+    fma,
+    r#"
+    fma(2; 3; 4), fma(.[]; 10; 1)
+    "#,
+    r#"
+    [1, 2]
+    "#,
+    r#"
+    10
+    11
+    21
+    "#
+);
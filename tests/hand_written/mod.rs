@@ -1,7 +1,11 @@
 use crate::test;
 
 mod assignments;
+mod compiled_query;
 mod math;
+mod module;
+mod module_file_system;
+mod native_function;
 mod regex;
 
 test!(
@@ -43,7 +47,7 @@ test!(
     null
     "#,
     r#"
-    "3.9 2147483647 2147483648.0"
+    "3.9 2147483647 2147483648"
     "#
 );
 
@@ -890,3 +894,201 @@ test!(
     ["y"]
     "#
 );
+
+test!(
+    object_key_insertion_order,
+    r#"
+    keys_unsorted, [.[]], [to_entries[].key], ([paths] | tojson), tojson
+    "#,
+    r#"
+    {"c": 1, "a": 2, "b": 3}
+    "#,
+    r#"
+    ["c", "a", "b"]
+    [1, 2, 3]
+    ["c", "a", "b"]
+    "[[\"c\"],[\"a\"],[\"b\"]]"
+    "{\"c\":1,\"a\":2,\"b\":3}"
+    "#
+);
+
+test!(
+    object_key_order_on_update,
+    r#"
+    (.a = 0 | .d = 4), (. + {a: 0, d: 4}), del(.a), with_entries(.), ({b: 0} + .) | tojson
+    "#,
+    r#"
+    {"c": 1, "a": 2, "b": 3}
+    "#,
+    r#"
+    "{\"c\":1,\"a\":0,\"b\":3,\"d\":4}"
+    "{\"c\":1,\"a\":0,\"b\":3,\"d\":4}"
+    "{\"c\":1,\"b\":3}"
+    "{\"c\":1,\"a\":2,\"b\":3}"
+    "{\"b\":3,\"c\":1,\"a\":2}"
+    "#
+);
+
+test!(
+    object_equality_ignores_key_order,
+    r#"
+    . == {b: 2, a: 1}, ([., {b: 2, a: 1}] | unique | length)
+    "#,
+    r#"
+    {"a": 1, "b": 2}
+    "#,
+    r#"
+    true
+    1
+    "#
+);
+
+#[cfg(feature = "arbitrary-precision")]
+test!(
+    number_literal_preserved,
+    r#"
+    tojson, (.[] | . + 0 | tojson), (.[0] == 1)
+    "#,
+    r#"
+    [1.000, 1234567890123456789, 100000000000000000000000000001]
+    "#,
+    r#"
+    "[1.000,1234567890123456789,100000000000000000000000000001]"
+    "1"
+    "1234567890123456789"
+    "100000000000000000000000000001"
+    true
+    "#
+);
+
+// serde_json normalizes the exponents of literals in JSON input, but not the ones in queries.
+#[cfg(feature = "arbitrary-precision")]
+test!(
+    number_literal_exponent,
+    r#"
+    tojson, ([1E2, 1.5e300, 2.50] | tojson)
+    "#,
+    r#"
+    [1E2, 1.5e300, 1.0E-3, 2.50, 0.125]
+    "#,
+    r#"
+    "[1e+2,1.5e+300,1.0e-3,2.50,0.125]"
+    "[1E2,1.5e300,2.50]"
+    "#
+);
+
+test!(
+    exact_integer_arithmetic,
+    r#"
+    9223372036854775807 + 1, -9223372036854775808 - 1, 4294967296 * 4294967296, 7 / 2, 6 / 3, -7 % 3
+    | tojson
+    "#,
+    r#"
+    null
+    "#,
+    r#"
+    "9223372036854775808"
+    "-9223372036854775809"
+    "18446744073709551616"
+    "3.5"
+    "2"
+    "-1"
+    "#
+);
+
+test!(
+    exact_integer_float_comparison,
+    r#"
+    [100000000000000000001 == 1e20, 1e20 == 100000000000000000000, 100000000000000000001 == 100000000000000000000],
+    [100000000000000000001 > 1e20, 1e20 < 100000000000000000001, 9007199254740993 > 9007199254740992.0, 1 < 1.5, -1 > -1.5]
+    "#,
+    r#"
+    null
+    "#,
+    r#"
+    [false, true, false]
+    [true, true, true, true, true]
+    "#
+);
+
+#[cfg(feature = "arbitrary-precision")]
+test!(
+    sort_and_unique_mixed_integers_and_floats,
+    r#"
+    [100000000000000000001, 1e20, 100000000000000000000]
+    | (sort, unique, (reverse | sort), (reverse | unique), ([.[1:], .[:1]] | add | unique))
+    | map(tojson)
+    "#,
+    r#"
+    null
+    "#,
+    r#"
+    ["1e20", "100000000000000000000", "100000000000000000001"]
+    ["1e20", "100000000000000000001"]
+    ["100000000000000000000", "1e20", "100000000000000000001"]
+    ["100000000000000000000", "100000000000000000001"]
+    ["1e20", "100000000000000000001"]
+    "#
+);
+
+#[cfg(feature = "arbitrary-precision")]
+test!(
+    negative_zero,
+    r#"
+    (.[] | tojson), ([-0, -0.0, (0 | -.)] | tojson), (-0 == 0)
+    "#,
+    r#"
+    [-0, -0.0]
+    "#,
+    r#"
+    "-0"
+    "-0.0"
+    "[-0,-0,-0]"
+    true
+    "#
+);
+
+test!(
+    tostream,
+    r#"
+    [tostream]
+    "#,
+    r#"
+    {"a": [1, {"b": 2}], "c": [], "d": {}}
+    "#,
+    r#"
+    [[["a",0],1],[["a",1,"b"],2],[["a",1,"b"]],[["a",1]],[["c"],[]],[["d"],{}],[["d"]]]
+    "#
+);
+
+test!(
+    fromstream,
+    r#"
+    fromstream(tostream), fromstream((1, [], {"a": [2]}) | tostream)
+    "#,
+    r#"
+    {"a": [1, {"b": 2}], "c": [], "d": {}}
+    "#,
+    r#"
+    {"a": [1, {"b": 2}], "c": [], "d": {}}
+    1
+    []
+    {"a": [2]}
+    "#
+);
+
+test!(
+    truncate_stream,
+    r#"
+    [1 | truncate_stream([[0], 1], [[1, 0], 2], [[1, 0]], [[1]])], [truncate_stream(1; tostream)], fromstream(truncate_stream(1; tostream))
+    "#,
+    r#"
+    [{"a": 1}, [2, 3]]
+    "#,
+    r#"
+    [[[0], 2], [[0]]]
+    [[["a"], 1], [["a"]], [[0], 2], [[1], 3], [[1]]]
+    {"a": 1}
+    [2, 3]
+    "#
+);
@@ -0,0 +1,276 @@
+use std::{collections::HashMap, path::Path};
+
+use xq::{
+    module_loader::{LoadedModule, ModuleLoadError, ModuleLoader, PreludeLoader, Result},
+    Value,
+};
+use xq_lang::{ast::Program, parse_program};
+
+use crate::common::run_test_with_module_loader;
+
+#[derive(Clone)]
+struct TestModuleLoader {
+    modules: HashMap<&'static str, &'static str>,
+    data: HashMap<&'static str, &'static str>,
+}
+
+impl ModuleLoader for TestModuleLoader {
+    fn prelude(&self) -> Result<Vec<Program>> {
+        PreludeLoader().prelude()
+    }
+
+    fn load_values(
+        &self,
+        path: &str,
+        _search: Option<Vec<String>>,
+        _importer: Option<&Path>,
+    ) -> Result<Vec<Value>> {
+        let source = self
+            .data
+            .get(path)
+            .ok_or_else(|| ModuleLoadError::NotFoundError(path.to_string()))?;
+        Ok(serde_json::de::Deserializer::from_str(source)
+            .into_iter::<Value>()
+            .collect::<std::result::Result<_, _>>()
+            .expect("test data should be a valid json"))
+    }
+
+    fn load_program(
+        &self,
+        path: &str,
+        _search: Option<Vec<String>>,
+        _importer: Option<&Path>,
+    ) -> Result<LoadedModule> {
+        let source = self
+            .modules
+            .get(path)
+            .ok_or_else(|| ModuleLoadError::NotFoundError(path.to_string()))?;
+        Ok(parse_program(source)?.into())
+    }
+}
+
+fn loader() -> TestModuleLoader {
+    let modules = HashMap::from([
+        ("a", r#"def f: "a::f"; def g($x): [f, $x];"#),
+        ("b", r#"include "a"; def h: g(1);"#),
+        ("c", r#"import "a" as a; def f: "c::f"; def g: [f, a::f];"#),
+        ("with_query", r#"def f: 1; f"#),
+        (
+            "with_header",
+            r#"
+            module {name: "with_header", version: 1};
+            import "a" as a {search: "./"};
+            include "b";
+            import "table" as $t;
+            def f: a::f;
+            "#,
+        ),
+        ("shadows_map", r#"def map(f): "shadowed";"#),
+        ("uses_map", r#"def h: map(. + 1);"#),
+        (
+            "uses_data",
+            r#"import "table" as $t; def lookup($k): $t[0][$k];"#,
+        ),
+    ]);
+    let data = HashMap::from([("table", r#"{"a": 1, "b": 2}"#), ("multi", "1 2 3")]);
+    TestModuleLoader { modules, data }
+}
+
+macro_rules! test_with_modules {
+    ($name: ident, $query: expr, $input: expr, $output: expr) => {
+        #[test]
+        fn $name() -> std::result::Result<(), Box<dyn std::error::Error>> {
+            run_test_with_module_loader($query, $input, $output, &loader())
+        }
+    };
+}
+
+test_with_modules!(
+    import_with_alias,
+    r#"
+    import "a" as x; x::f, x::g(2)
+    "#,
+    r#"
+    null
+    "#,
+    r#"
+    "a::f"
+    ["a::f", 2]
+    "#
+);
+
+test_with_modules!(
+    include_into_current_scope,
+    r#"
+    include "a"; f, g(2)
+    "#,
+    r#"
+    null
+    "#,
+    r#"
+    "a::f"
+    ["a::f", 2]
+    "#
+);
+
+test_with_modules!(
+    include_is_reexported,
+    r#"
+    import "b" as b; b::h, b::f
+    "#,
+    r#"
+    null
+    "#,
+    r#"
+    ["a::f", 1]
+    "a::f"
+    "#
+);
+
+test_with_modules!(
+    import_is_not_reexported,
+    r#"
+    import "c" as c; def f: "main::f"; f, c::f, c::g
+    "#,
+    r#"
+    null
+    "#,
+    r#"
+    "main::f"
+    "c::f"
+    ["c::f", "a::f"]
+    "#
+);
+
+test_with_modules!(
+    module_does_not_see_importer_definitions,
+    r#"
+    include "shadows_map"; include "uses_map"; map(.), h
+    "#,
+    r#"
+    [1, 2]
+    "#,
+    r#"
+    "shadowed"
+    [2, 3]
+    "#
+);
+
+test_with_modules!(
+    import_data,
+    r#"
+    import "table" as $t; import "multi" as $m; $t, $t::t, $m, $t[0][.]
+    "#,
+    r#"
+    "b"
+    "#,
+    r#"
+    [{"a": 1, "b": 2}]
+    [{"a": 1, "b": 2}]
+    [1, 2, 3]
+    2
+    "#
+);
+
+test_with_modules!(
+    import_data_in_module,
+    r#"
+    import "uses_data" as u; u::lookup(.)
+    "#,
+    r#"
+    "a"
+    "b"
+    "#,
+    r#"
+    1
+    2
+    "#
+);
+
+#[test]
+fn data_import_is_not_exported() {
+    assert!(
+        run_test_with_module_loader(r#"include "uses_data"; $t"#, "null", "null", &loader())
+            .is_err()
+    );
+}
+
+#[test]
+fn import_unknown_module() {
+    assert!(
+        run_test_with_module_loader(r#"import "z" as z; ."#, "null", "null", &loader()).is_err()
+    );
+}
+
+#[test]
+fn import_module_with_query() {
+    assert!(run_test_with_module_loader(
+        r#"import "with_query" as w; ."#,
+        "null",
+        "null",
+        &loader()
+    )
+    .is_err());
+}
+
+test_with_modules!(
+    import_module_with_header,
+    r#"
+    import "with_header" as w; w::f
+    "#,
+    r#"
+    null
+    "#,
+    r#"
+    "a::f"
+    "#
+);
+
+test_with_modules!(
+    main_program_with_header,
+    r#"
+    module {name: "main"}; 1
+    "#,
+    r#"
+    null
+    "#,
+    r#"
+    1
+    "#
+);
+
+test_with_modules!(
+    modulemeta,
+    r#"
+    "with_header", "a" | modulemeta
+    "#,
+    r#"
+    null
+    "#,
+    r#"
+    {
+        "name": "with_header",
+        "version": 1,
+        "deps": [
+            {"search": "./", "as": "a", "is_data": false, "relpath": "a"},
+            {"is_data": false, "relpath": "b"},
+            {"as": "t", "is_data": true, "relpath": "table"}
+        ]
+    }
+    {"deps": []}
+    "#
+);
+
+test_with_modules!(
+    modulemeta_unknown_module,
+    r#"
+    try ("z" | modulemeta) catch "not found", try (1 | modulemeta) catch "invalid"
+    "#,
+    r#"
+    null
+    "#,
+    r#"
+    "not found"
+    "invalid"
+    "#
+);
@@ -0,0 +1,156 @@
+use xq::{
+    compile::compiler::CompileError,
+    module_loader::{FileSystemModuleLoader, ModuleLoadError},
+    run_query, Value, XQError,
+};
+
+use crate::common::run_test_with_module_loader;
+
+fn loader() -> FileSystemModuleLoader {
+    FileSystemModuleLoader::new(["tests/modules"]).with_origin("tests/modules")
+}
+
+macro_rules! test_with_modules {
+    ($name: ident, $query: expr, $input: expr, $output: expr) => {
+        #[test]
+        fn $name() -> std::result::Result<(), Box<dyn std::error::Error>> {
+            run_test_with_module_loader($query, $input, $output, &loader())
+        }
+    };
+}
+
+fn load_error(query: &str) -> ModuleLoadError {
+    let input = std::iter::empty::<Result<Value, xq::InputError>>();
+    match run_query(query, input, std::iter::empty(), &loader()) {
+        Err(XQError::CompileError(CompileError::ModuleLoadError(e))) => e,
+        Err(e) => panic!("unexpected error: {e:?}"),
+        Ok(_) => panic!("query should fail to compile"),
+    }
+}
+
+test_with_modules!(
+    import_file_module,
+    r#"
+    import "greet" as g; g::hello
+    "#,
+    r#"
+    null
+    "#,
+    r#"
+    "hello"
+    "#
+);
+
+test_with_modules!(
+    import_directory_module,
+    r#"
+    import "lib" as lib; lib::answer
+    "#,
+    r#"
+    null
+    "#,
+    r#"
+    42
+    "#
+);
+
+test_with_modules!(
+    import_nested_module,
+    r#"
+    include "nested/util"; shout
+    "#,
+    r#"
+    null
+    "#,
+    r#"
+    "HELLO"
+    "#
+);
+
+test_with_modules!(
+    import_with_search_meta,
+    r#"
+    import "extra" as e {search: "./searched"}; e::extra
+    "#,
+    r#"
+    null
+    "#,
+    r#"
+    "extra"
+    "#
+);
+
+test_with_modules!(
+    import_relative_to_importing_module,
+    r#"
+    import "relative/sub/mod" as m; m::f
+    "#,
+    r#"
+    null
+    "#,
+    r#"
+    ["help", "value"]
+    "#
+);
+
+test_with_modules!(
+    import_file_data,
+    r#"
+    import "data" as $d; [$d[].x], $d::d[1]
+    "#,
+    r#"
+    null
+    "#,
+    r#"
+    [1, 2]
+    {"x": 2}
+    "#
+);
+
+#[test]
+fn import_not_found() {
+    assert!(matches!(
+        load_error(r#"import "missing" as m; ."#),
+        ModuleLoadError::NotFoundError(path) if path == "missing"
+    ));
+}
+
+#[test]
+fn import_not_found_without_search_meta() {
+    assert!(matches!(
+        load_error(r#"import "extra" as e; ."#),
+        ModuleLoadError::NotFoundError(_)
+    ));
+}
+
+#[test]
+fn import_cycle() {
+    assert!(matches!(
+        load_error(r#"import "cycle_a" as a; ."#),
+        ModuleLoadError::CyclicImportError(path, _) if path == "cycle_a"
+    ));
+}
+
+#[test]
+fn import_invalid_path() {
+    for path in ["../greet", "/greet", "lib/lib"] {
+        assert!(matches!(
+            load_error(&format!(r#"import "{path}" as m; ."#)),
+            ModuleLoadError::InvalidPathError(..)
+        ));
+    }
+}
+
+test_with_modules!(
+    modulemeta_from_file,
+    r#"
+    "greet", "nested/util" | modulemeta
+    "#,
+    r#"
+    null
+    "#,
+    r#"
+    {"description": "Greetings", "deps": []}
+    {"deps": [{"as": "g", "is_data": false, "relpath": "greet"}]}
+    "#
+);
@@ -0,0 +1,309 @@
+use xq::{
+    compile::compiler::Compiler,
+    module_loader::PreludeLoader,
+    vm::{
+        machine::{CancellationToken, Limits},
+        QueryExecutionError,
+    },
+    CompiledQuery, InputError, Value,
+};
+
+use crate::common::{run_with_limits, values};
+
+fn run(compiler: Compiler, query: &str, input: &str) -> Result<Vec<Value>, QueryExecutionError> {
+    let mut compiler = compiler;
+    let parsed = xq_lang::parse_program(query).unwrap();
+    let program = compiler.compile(&parsed, &PreludeLoader()).unwrap();
+    let input = values(input).into_iter().map(Ok::<_, InputError>);
+    CompiledQuery::new(program)
+        .run(input, std::iter::empty())
+        .collect()
+}
+
+fn compiler() -> Compiler {
+    let mut compiler = Compiler::new();
+    compiler.register_fn("tenant_name", 0, |context, _| match context {
+        Value::Number(n) => Ok(format!("tenant-{n}").into()),
+        _ => Err(QueryExecutionError::UserDefinedError(
+            "tenant id should be a number".to_string().into(),
+        )),
+    });
+    compiler.register_fn("add", 2, |context, args| {
+        let mut sum = context;
+        for arg in args {
+            sum = match (sum, arg) {
+                (Value::Number(lhs), Value::Number(rhs)) => (lhs + rhs.clone()).into(),
+                _ => return Ok(Value::Null),
+            }
+        }
+        Ok(sum)
+    });
+    compiler.register_fn("nothing", 0, |_, _| {
+        Err(QueryExecutionError::UserDefinedError(Value::Null))
+    });
+    compiler.register_fn("length", 0, |_, _| Ok("overridden".to_string().into()));
+    compiler.register_fn("map", 1, |_, _| Ok("not used".to_string().into()));
+    compiler.register_generator("kv_scan", 1, |context, args| {
+        let store = ["apple", "apricot", "banana", "blueberry", "cherry"];
+        let prefixes = args[0].run(context).collect::<Result<Vec<_>, _>>()?;
+        let keys = prefixes.into_iter().flat_map(move |prefix| {
+            store.into_iter().filter_map(move |key| match &prefix {
+                Value::String(prefix) if key.starts_with(prefix.as_str()) => {
+                    Some(Ok(key.to_string().into()))
+                }
+                Value::String(_) => None,
+                prefix => Some(Err(QueryExecutionError::UserDefinedError(prefix.clone()))),
+            })
+        });
+        Ok(Box::new(keys))
+    });
+    compiler.register_generator("naturals", 0, |_, _| {
+        Ok(Box::new((0..).map(|n: i32| Ok(Value::number(n)))))
+    });
+    compiler.register_generator("each_result", 1, |context, args| {
+        Ok(Box::new(args[0].run(context)))
+    });
+    compiler
+}
+
+#[test]
+fn call_native_function() {
+    assert_eq!(
+        run(compiler(), "tenant_name", "1 2").unwrap(),
+        values(r#""tenant-1" "tenant-2""#)
+    );
+}
+
+#[test]
+fn call_native_function_with_args() {
+    assert_eq!(
+        run(compiler(), "add(1, 2; 10, 20)", "100").unwrap(),
+        values("111 121 112 122")
+    );
+}
+
+#[test]
+fn native_function_error() {
+    assert!(run(compiler(), "tenant_name", r#""x""#).is_err());
+    assert_eq!(
+        run(compiler(), r#"try tenant_name catch "err""#, r#""x""#).unwrap(),
+        values(r#""err""#)
+    );
+}
+
+#[test]
+fn native_function_backtrack() {
+    assert_eq!(
+        run(compiler(), "[1, nothing, 2]", "null").unwrap(),
+        values("[1, 2]")
+    );
+}
+
+#[test]
+fn native_function_precedence() {
+    assert_eq!(
+        run(
+            compiler(),
+            "length, map(. + 1), (def length: 0; length)",
+            "[1]"
+        )
+        .unwrap(),
+        values(r#""overridden" [2] 0"#)
+    );
+}
+
+#[test]
+fn override_input_filename() {
+    assert_eq!(
+        run(Compiler::new(), "input_filename, input_line_number", "1").unwrap(),
+        values("null null")
+    );
+    let mut compiler = Compiler::new();
+    compiler.register_fn("input_filename", 0, |_, _| {
+        Ok(Value::string("data.json".to_string()))
+    });
+    assert_eq!(
+        run(compiler, "input_filename", "1").unwrap(),
+        values(r#""data.json""#)
+    );
+}
+
+#[test]
+fn unregistered_native_function() {
+    let parsed = xq_lang::parse_program("tenant_name").unwrap();
+    assert!(Compiler::new().compile(&parsed, &PreludeLoader()).is_err());
+}
+
+#[test]
+fn call_native_generator() {
+    assert_eq!(
+        run(
+            compiler(),
+            r#"[kv_scan("ap")], [kv_scan("b", "c")], [kv_scan("x")]"#,
+            "null"
+        )
+        .unwrap(),
+        values(r#"["apple", "apricot"] ["banana", "blueberry", "cherry"] []"#)
+    );
+}
+
+#[test]
+fn native_generator_closure_arg() {
+    assert_eq!(
+        run(
+            compiler(),
+            r#"1 as $x | [each_result(., . + $x, empty)]"#,
+            "10"
+        )
+        .unwrap(),
+        values("[10, 11]")
+    );
+    assert_eq!(
+        run(compiler(), r#"[kv_scan(.[])]"#, r#"["ch", "apr"]"#).unwrap(),
+        values(r#"["cherry", "apricot"]"#)
+    );
+}
+
+#[test]
+fn native_generator_backtrack() {
+    assert_eq!(
+        run(
+            compiler(),
+            r#"[limit(3; naturals)], first(kv_scan("b")), [naturals | select(. % 2 == 0) | if . > 6 then error else . end]?"#,
+            "null"
+        )
+        .unwrap(),
+        values(r#"[0, 1, 2] "banana""#)
+    );
+    assert_eq!(
+        run(
+            compiler(),
+            r#"label $out | naturals | if . < 2 then . else break $out end"#,
+            "null"
+        )
+        .unwrap(),
+        values("0 1")
+    );
+}
+
+#[test]
+fn native_generator_error() {
+    assert_eq!(
+        run(
+            compiler(),
+            r#"[.[] | try kv_scan(.) catch "err"]"#,
+            r#"["ap", 1]"#
+        )
+        .unwrap(),
+        values(r#"["apple", "apricot", "err"]"#)
+    );
+    assert!(run(compiler(), r#"each_result(error("x"))"#, "null").is_err());
+}
+
+fn run_with_fuel(
+    query: &str,
+    fuel: u64,
+) -> xq::vm::machine::ResultIterator<
+    impl Iterator<Item = Result<Value, InputError>>,
+    std::iter::Empty<Result<Value, InputError>>,
+> {
+    let parsed = xq_lang::parse_program(query).unwrap();
+    let program = compiler().compile(&parsed, &PreludeLoader()).unwrap();
+    let input = values("null").into_iter().map(Ok::<_, InputError>);
+    CompiledQuery::new(program)
+        .with_fuel(fuel)
+        .run(input, std::iter::empty())
+}
+
+#[test]
+fn native_generator_closure_consumes_fuel() {
+    let mut results = run_with_fuel("[each_result(range(100))]", 100000);
+    assert!(matches!(results.next(), Some(Ok(_))));
+    let mut empty = run_with_fuel("[each_result(empty)]", 100000);
+    assert!(matches!(empty.next(), Some(Ok(_))));
+    assert!(results.fuel().unwrap() < empty.fuel().unwrap());
+
+    for query in [
+        "each_result(last(range(1e18)))",
+        "[kv_scan(last(range(1e18)))]",
+        "try each_result(last(range(1e18))) catch 0",
+    ] {
+        let mut results = run_with_fuel(query, 100000);
+        assert!(
+            matches!(results.next(), Some(Err(QueryExecutionError::OutOfFuel))),
+            "{query}"
+        );
+        assert_eq!(results.fuel(), Some(0));
+        results.add_fuel(1000);
+        assert!(results.next().is_none(), "{query}");
+    }
+}
+
+#[test]
+fn native_generator_closure_halt_and_cancel_are_not_caught() {
+    let mut results = run(
+        compiler(),
+        r#"try each_result(halt_error(3)) catch "caught", "after""#,
+        r#""bye""#,
+    );
+    assert!(matches!(
+        results,
+        Err(QueryExecutionError::Halt { exit_code: 3, .. })
+    ));
+    results = run(compiler(), r#"[try kv_scan(halt) catch "caught"]"#, "null");
+    assert!(matches!(
+        results,
+        Err(QueryExecutionError::Halt { exit_code: 0, .. })
+    ));
+
+    let token = CancellationToken::new();
+    let mut compiler = compiler();
+    let cancelling = token.clone();
+    compiler.register_fn("cancel", 0, move |context, _| {
+        cancelling.cancel();
+        Ok(context)
+    });
+    let parsed = xq_lang::parse_program(r#"try each_result(cancel, 1) catch "caught""#).unwrap();
+    let program = compiler.compile(&parsed, &PreludeLoader()).unwrap();
+    let input = values("null").into_iter().map(Ok::<_, InputError>);
+    let results: Vec<_> = CompiledQuery::new(program)
+        .with_cancellation_token(token)
+        .run(input, std::iter::empty())
+        .collect();
+    assert!(matches!(results[..], [Err(QueryExecutionError::Cancelled)]));
+}
+
+#[test]
+fn native_generator_closure_counts_toward_limits() {
+    let limits = Limits {
+        max_frame_depth: Some(100),
+        ..Limits::default()
+    };
+    assert!(matches!(
+        run_with_limits(compiler(), "def f: each_result(1 + f); f", limits)[..],
+        [Err(QueryExecutionError::FrameDepthLimitExceeded(100))]
+    ));
+    let limits = Limits {
+        max_forks: Some(100),
+        ..Limits::default()
+    };
+    assert!(matches!(
+        run_with_limits(
+            compiler(),
+            "def f(n): if n == 0 then n else (0, 1) as $x | each_result(f(n - 1)) end; first(f(1000))",
+            limits
+        )[..],
+        [Err(QueryExecutionError::ForkLimitExceeded(100))]
+    ));
+    assert_eq!(
+        run_with_limits(
+            compiler(),
+            "[each_result(limit(10; repeat(1)))] | add",
+            limits
+        )
+        .into_iter()
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap(),
+        values("10")
+    );
+}